@@ -0,0 +1,136 @@
+//! Micro-benchmarks for the move code, the heuristic and the search. Every input comes from a
+//! fixed seed so numbers can be compared between commits.
+
+#[macro_use]
+extern crate criterion;
+
+use criterion::{BatchSize, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use auto2048::agent::{Agent, GreedyAgent, SearchAgent};
+use auto2048::ai::{AIScore, AI};
+use auto2048::game::{combine_line, Board, Direction, Game};
+use auto2048::spawner::SeededSpawner;
+
+const SEED: u64 = 2048;
+
+/// A 4x4 board with `filled` tiles of up to 1024 on random cells.
+fn seeded_board(rng: &mut StdRng, filled: usize) -> Board {
+    let mut cells: Vec<Option<u32>> = (0..16)
+        .map(|i| {
+            if i < filled {
+                Some(rng.gen_range(0, 10))
+            } else {
+                None
+            }
+        })
+        .collect();
+    cells.shuffle(rng);
+    let rows: Vec<Vec<Option<u32>>> = cells.chunks(4).map(|row| row.to_vec()).collect();
+    Board::from_exponents(&rows).unwrap()
+}
+
+fn seeded_boards(count: usize, filled: usize) -> Vec<Board> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    (0..count).map(|_| seeded_board(&mut rng, filled)).collect()
+}
+
+fn moves(c: &mut Criterion) {
+    let boards = seeded_boards(64, 10);
+    c.bench_function("combine_line", |b| {
+        b.iter_batched(
+            || boards.clone(),
+            |mut boards| {
+                for board in boards.iter_mut() {
+                    combine_line(&mut board.get_mut_row(0));
+                }
+                boards
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    let mut group = c.benchmark_group("step_rows");
+    for &dir in Direction::all().iter() {
+        group.bench_with_input(BenchmarkId::from_parameter(dir), &dir, |b, &dir| {
+            b.iter_batched(
+                || boards.clone(),
+                |mut boards| {
+                    for board in boards.iter_mut() {
+                        board.step_rows(dir);
+                    }
+                    boards
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn heuristic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_ai_score");
+    for &filled in [4, 10, 16].iter() {
+        let boards = seeded_boards(64, filled);
+        group.bench_with_input(BenchmarkId::new("tiles", filled), &boards, |b, boards| {
+            b.iter(|| boards.iter().map(|board| board.get_ai_score()).sum::<f64>())
+        });
+    }
+    group.finish();
+}
+
+/// The tree grows while it is searched, so `build_tree` times a search from an empty tree and
+/// `minimax` a search over the tree an earlier search already built.
+fn search(c: &mut Criterion) {
+    let board = seeded_boards(1, 6).remove(0);
+    let mut group = c.benchmark_group("search");
+    group.sample_size(10);
+    for depth in 2..=8 {
+        group.bench_with_input(
+            BenchmarkId::new("build_tree", depth),
+            &depth,
+            |b, &depth| {
+                b.iter(|| {
+                    let mut ai = AI::new(&board, depth);
+                    ai.build_tree();
+                    ai.minimax(None)
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("minimax", depth), &depth, |b, &depth| {
+            let mut ai = AI::new(&board, depth);
+            ai.build_tree();
+            ai.minimax(None);
+            b.iter(|| ai.minimax(None))
+        });
+    }
+    group.finish();
+}
+
+/// Plays a whole game from `start` with spawns from `SEED`, returning the number of moves.
+fn play(start: &Board, agent: &mut dyn Agent) -> u32 {
+    let mut game = Game::with_target(11, true);
+    game.set_board(start.clone());
+    let mut spawner = SeededSpawner::new(SEED);
+    while let Some(dir) = agent.choose_move(&game) {
+        game.step_with(dir, &mut spawner);
+    }
+    game.moves()
+}
+
+/// Whole games from the same start and spawns, the greedy agent keeps the search out of it.
+fn games(c: &mut Criterion) {
+    let start = seeded_boards(1, 2).remove(0);
+    let mut group = c.benchmark_group("game");
+    group.sample_size(10);
+    group.bench_function("greedy", |b| b.iter(|| play(&start, &mut GreedyAgent)));
+    group.bench_function("minimax_depth_2", |b| {
+        b.iter(|| play(&start, &mut SearchAgent::minimax(&start, 2)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, moves, heuristic, search, games);
+criterion_main!(benches);
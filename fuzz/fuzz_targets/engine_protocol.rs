@@ -0,0 +1,33 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use auto2048::engine::{Engine, Request};
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    // One request per line, like `Engine::run` reads them from stdin.
+    let mut engine = Engine::new();
+    for line in s.lines() {
+        let request = match serde_json::from_str::<Request>(line) {
+            Ok(Request::Quit) => break,
+            // Deep searches are slow rather than wrong, keep them shallow so runs don't time out.
+            // Without an agent or a depth the engine would search to its default depth.
+            Ok(Request::Best { depth, agent, .. }) => Request::Best {
+                depth: match (depth, &agent) {
+                    (Some(depth), _) => Some(depth.min(2)),
+                    (None, Some(_)) => None,
+                    (None, None) => Some(1),
+                },
+                time_ms: None,
+                agent,
+            },
+            Ok(request) => request,
+            Err(_) => continue,
+        };
+        let response = engine.respond(request);
+        assert!(response["ok"].is_boolean());
+    }
+});
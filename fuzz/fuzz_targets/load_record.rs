@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use auto2048::record::Record;
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let record = match s.parse::<Record>() {
+        Ok(record) => record,
+        Err(_) => return,
+    };
+    assert_eq!(record.to_string().parse::<Record>().as_ref(), Ok(&record));
+    if let Ok(game) = record.replay() {
+        let _ = game.get_score();
+    }
+});
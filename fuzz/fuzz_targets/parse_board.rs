@@ -0,0 +1,33 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use auto2048::game::{Board, Direction, Notation};
+
+fuzz_target!(|data: &[u8]| {
+    let s = match std::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let _ = s.parse::<Board>();
+    for &notation in [Notation::Exponents, Notation::Values, Notation::Compact].iter() {
+        let board = match Board::parse(s, notation) {
+            Ok(board) => board,
+            Err(_) => continue,
+        };
+        // Whatever parses prints back to the same board in every notation.
+        for &out in [Notation::Exponents, Notation::Values, Notation::Compact].iter() {
+            assert_eq!(
+                Board::parse(&board.to_notation(out), out).as_ref(),
+                Ok(&board)
+            );
+        }
+        let _ = board.canonical();
+        for &dir in Direction::all().iter() {
+            let mut moved = board.clone();
+            if moved.step_rows(dir) {
+                moved.step_add(dir);
+            }
+            let _ = moved.to_notation(Notation::Values);
+        }
+    }
+});
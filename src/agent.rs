@@ -0,0 +1,176 @@
+use rand::{prelude::SliceRandom, thread_rng, Rng};
+
+use std::io;
+use std::path::Path;
+
+use crate::ai::{AIScore, MinMaxResult, Searcher, AI};
+use crate::game::{Board, Direction, Game};
+#[cfg(feature = "learning")]
+use crate::learning::{Learning, Reward};
+use crate::mcts::{Mcts, MctsConfig};
+use crate::monte_carlo::{MonteCarlo, MonteCarloConfig};
+
+pub const DEFAULT_DEPTH: u32 = 6;
+/// Training batches run before the learned policy is used.
+pub const DEFAULT_BATCHES: usize = 10;
+
+/// Names accepted by `by_name`.
+#[cfg(feature = "learning")]
+pub const AGENTS: &[&str] = &[
+    "minimax", "mcts", "mc", "random", "greedy", "noise", "learned",
+];
+#[cfg(not(feature = "learning"))]
+pub const AGENTS: &[&str] = &["minimax", "mcts", "mc", "random", "greedy", "noise"];
+
+/// Anything that can pick the next move of a game.
+pub trait Agent {
+    fn name(&self) -> &str;
+
+    /// `None` when the game has no legal moves left.
+    fn choose_move(&mut self, game: &Game) -> Option<Direction>;
+
+    /// The search behind the last move, for agents that search.
+    fn last_search(&self) -> Option<&MinMaxResult> {
+        None
+    }
+
+    fn export_dot(&self, _path: &Path, _max_depth: u32) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("the {} agent keeps no search tree", self.name()),
+        ))
+    }
+}
+
+/// Builds an agent with its default settings, the learned policy first trains for
+/// `DEFAULT_BATCHES`.
+pub fn by_name(name: &str, board: &Board) -> Result<Box<dyn Agent>, String> {
+    Ok(match name {
+        "minimax" => Box::new(SearchAgent::minimax(board, DEFAULT_DEPTH)),
+        "mcts" => Box::new(SearchAgent::mcts(board, MctsConfig::default())),
+        "mc" => Box::new(SearchAgent::monte_carlo(board, MonteCarloConfig::default())),
+        "random" => Box::new(RandomAgent),
+        "greedy" => Box::new(GreedyAgent),
+        "noise" => Box::new(SearchAgent::noise(board, DEFAULT_DEPTH)),
+        #[cfg(feature = "learning")]
+        "learned" => Learning::train(Some(DEFAULT_BATCHES), Reward::default()),
+        #[cfg(not(feature = "learning"))]
+        "learned" => return Err("built without the learning feature".to_string()),
+        _ => return Err(format!("unknown agent '{}'", name)),
+    })
+}
+
+/// Plays the best move of a `Searcher`, the tree is kept between moves.
+pub struct SearchAgent {
+    name: &'static str,
+    searcher: Box<dyn Searcher>,
+    last: Option<MinMaxResult>,
+    played: Option<Direction>,
+}
+
+impl SearchAgent {
+    pub fn new(name: &'static str, searcher: Box<dyn Searcher>) -> SearchAgent {
+        SearchAgent {
+            name,
+            searcher,
+            last: None,
+            played: None,
+        }
+    }
+
+    pub fn minimax(board: &Board, depth: u32) -> SearchAgent {
+        let mut ai = AI::new(board, depth);
+        ai.build_tree();
+        SearchAgent::new("minimax", Box::new(ai))
+    }
+
+    /// Minimax over random evaluations, the baseline `rand` measures.
+    pub fn noise(board: &Board, depth: u32) -> SearchAgent {
+        let mut ai = AI::new(board, depth);
+        ai.set_heuristic(Box::new(|_: &Board| thread_rng().gen_range(-10f64, 10f64)));
+        ai.build_tree();
+        SearchAgent::new("noise", Box::new(ai))
+    }
+
+    pub fn mcts(board: &Board, config: MctsConfig) -> SearchAgent {
+        let mut mcts = Mcts::new(board, config);
+        mcts.build_tree();
+        SearchAgent::new("mcts", Box::new(mcts))
+    }
+
+    pub fn monte_carlo(board: &Board, config: MonteCarloConfig) -> SearchAgent {
+        SearchAgent::new("mc", Box::new(MonteCarlo::new(board, config)))
+    }
+}
+
+impl Agent for SearchAgent {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Direction> {
+        // Usually the game went on with our own move, `set_board` catches the cases where it
+        // didn't.
+        let board = game.get_board();
+        if let Some(dir) = self.played.take() {
+            let spawn = game
+                .last_spawn()
+                .map(|(x, y)| ((x, y), board.get_cell(x, y).get_score().unwrap_or(0)));
+            self.searcher.advance(dir, spawn);
+        }
+        self.searcher.set_board(board);
+        // A hidden tile is left to the search to average over.
+        if game.rules().previews_next_tile() {
+            self.searcher.set_next_tile(game.next_tile());
+        }
+        let result = self.searcher.think();
+        self.played = result.get_direction();
+        self.last = Some(result);
+        self.played
+    }
+
+    fn last_search(&self) -> Option<&MinMaxResult> {
+        self.last.as_ref()
+    }
+
+    fn export_dot(&self, path: &Path, max_depth: u32) -> io::Result<()> {
+        self.searcher.export_dot(path, max_depth)
+    }
+}
+
+pub struct RandomAgent;
+
+impl Agent for RandomAgent {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Direction> {
+        game.get_board()
+            .legal_moves()
+            .choose(&mut thread_rng())
+            .cloned()
+    }
+}
+
+/// Plays the move whose slide scores best on the heuristic, without looking at spawns.
+pub struct GreedyAgent;
+
+impl Agent for GreedyAgent {
+    fn name(&self) -> &str {
+        "greedy"
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Direction> {
+        let mut best: Option<(Direction, f64)> = None;
+        for dir in game.get_board().legal_moves() {
+            let mut board = game.get_board().clone();
+            board.step_rows(dir);
+            let score = board.get_ai_score();
+            if best.map_or(true, |(_, best)| score > best) {
+                best = Some((dir, score));
+            }
+        }
+        best.map(|(dir, _)| dir)
+    }
+}
@@ -1,13 +1,24 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use crate::game::{Board, Direction};
+use crate::game::{Board, Cell, Direction};
+
+type NodeId = usize;
+
+/// Leaf evaluations remembered per search, the table starts over once it holds this many.
+const TRANSPOSITION_CAPACITY: usize = 1 << 16;
 
 pub struct AINode {
     board: Board,
-    layer: u32,
-    options: Option<Vec<Rc<RefCell<AINode>>>>,
+    options: Option<Vec<NodeId>>,
     player: Player,
+    spawn: Option<(usize, usize)>,
+    value: Option<f64>,
 }
 
 enum Player {
@@ -16,37 +27,53 @@ enum Player {
 }
 
 impl AINode {
-    fn new(board: Board, layer: u32, player: Player) -> AINode {
+    fn new(board: Board, player: Player) -> AINode {
         AINode {
             board,
-            layer,
             options: None,
             player,
+            spawn: None,
+            value: None,
         }
     }
 
-    fn add_option(&mut self, node: AINode) {
-        if self.options.is_none() {
-            self.options = Some(Vec::new());
+    /// The tile this spawn placed.
+    fn spawned(&self) -> Option<u32> {
+        let (x, y) = self.spawn?;
+        self.board.get_cell(x, y).get_score()
+    }
+
+    fn step(&self) -> Option<PvStep> {
+        match (&self.player, self.spawn) {
+            (Player::Max(dir), _) => Some(PvStep::Move(*dir)),
+            (Player::Min, Some((x, y))) => {
+                let tile = self
+                    .spawned()
+                    .map_or(0, |tile| self.board.rules().tile_value(tile));
+                Some(PvStep::Spawn(x, y, tile))
+            }
+            (Player::Min, None) => None,
         }
-        self.options
-            .as_mut()
-            .unwrap()
-            .push(Rc::new(RefCell::new(node)));
     }
 
-    fn add_layer(&mut self) {
+    /// The positions one ply on, spawns only place `tile` when it is known.
+    fn children(&self, tile: Option<u32>) -> Vec<AINode> {
+        let mut children = Vec::new();
         match self.player {
             Player::Max(dir) => {
-                let max = match dir {
-                    Direction::LEFT | Direction::RIGHT => self.board.height(),
-                    Direction::DOWN | Direction::UP => self.board.width(),
+                let tiles = match tile {
+                    Some(tile) => vec![(tile, 1.0)],
+                    None => self.board.rules().spawn_tiles(),
                 };
-                for i in 0..max {
-                    let mut board = self.board.clone();
-                    let ok = board.step_add_index(dir, i);
-                    if ok {
-                        self.add_option(AINode::new(board, self.layer + 1, Player::Min));
+                for i in self.board.free_spawns(dir) {
+                    for &(tile, _) in tiles.iter() {
+                        let mut board = self.board.clone();
+                        if board.spawn_at(dir, i, tile) {
+                            let spawn = board.spawn_cell(dir, i);
+                            let mut node = AINode::new(board, Player::Min);
+                            node.spawn = Some(spawn);
+                            children.push(node);
+                        }
                     }
                 }
             }
@@ -60,49 +87,191 @@ impl AINode {
                 for dir in dirs {
                     let mut board = self.board.clone();
                     if board.step_rows(dir) {
-                        self.add_option(AINode::new(board, self.layer + 1, Player::Max(dir)))
+                        children.push(AINode::new(board, Player::Max(dir)));
                     }
                 }
             }
         }
+        children
     }
+}
 
-    fn build_sub_tree(&mut self, max_depth: u32) {
-        if self.options.is_some() {
-            for option in self.options.as_mut().unwrap() {
-                option.borrow_mut().build_tree(max_depth);
-            }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PvStep {
+    Move(Direction),
+    /// The cell and the value of the spawned tile.
+    Spawn(usize, usize, u64),
+}
+
+impl fmt::Display for PvStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PvStep::Move(dir) => write!(f, "{}", dir),
+            PvStep::Spawn(x, y, tile) => write!(f, "spawn {} at {},{}", tile, x, y),
         }
     }
+}
 
-    fn build_tree(&mut self, max_depth: u32) {
-        self.add_layer();
-        if self.layer < max_depth {
-            self.build_sub_tree(max_depth);
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchStats {
+    pub searches: usize,
+    pub nodes_per_depth: Vec<usize>,
+    pub leaf_evaluations: usize,
+    pub cutoffs: usize,
+    pub transposition_hits: usize,
+    pub nodes_created: usize,
+    pub nodes_reused: usize,
+    pub tree_nodes: usize,
+    pub peak_memory: usize,
+}
+
+impl SearchStats {
+    pub fn visit(&mut self, layer: u32) {
+        let layer = layer as usize;
+        if self.nodes_per_depth.len() <= layer {
+            self.nodes_per_depth.resize(layer + 1, 0);
         }
+        self.nodes_per_depth[layer] += 1;
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes_per_depth.iter().sum()
+    }
+
+    /// `b` such that a uniform tree of the searched depth would have as many nodes on its
+    /// deepest layer as this search did, per search.
+    pub fn branching_factor(&self) -> f64 {
+        let depth = self.nodes_per_depth.len().saturating_sub(1);
+        if depth == 0 || self.searches == 0 {
+            return 0.0;
+        }
+        let leaves = self.nodes_per_depth[depth] as f64 / self.searches as f64;
+        leaves.powf(1.0 / depth as f64)
+    }
+
+    /// Sums the counters of `other` into these, memory and tree size keep their peak.
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.searches += other.searches;
+        if self.nodes_per_depth.len() < other.nodes_per_depth.len() {
+            self.nodes_per_depth.resize(other.nodes_per_depth.len(), 0);
+        }
+        for (total, nodes) in self
+            .nodes_per_depth
+            .iter_mut()
+            .zip(other.nodes_per_depth.iter())
+        {
+            *total += nodes;
+        }
+        self.leaf_evaluations += other.leaf_evaluations;
+        self.cutoffs += other.cutoffs;
+        self.transposition_hits += other.transposition_hits;
+        self.nodes_created += other.nodes_created;
+        self.nodes_reused += other.nodes_reused;
+        self.tree_nodes = self.tree_nodes.max(other.tree_nodes);
+        self.peak_memory = self.peak_memory.max(other.peak_memory);
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let per_depth: Vec<String> = self.nodes_per_depth.iter().map(|n| n.to_string()).collect();
+        write!(
+            f,
+            "nodes {} [{}] leaves {} cutoffs {} tt hits {} ebf {:.2} created {} reused {} tree {} ({} KiB)",
+            self.nodes(),
+            per_depth.join(" "),
+            self.leaf_evaluations,
+            self.cutoffs,
+            self.transposition_hits,
+            self.branching_factor(),
+            self.nodes_created,
+            self.nodes_reused,
+            self.tree_nodes,
+            self.peak_memory / 1024
+        )
     }
 }
 
 pub struct MinMaxResult {
     score: f64,
-    node: Rc<RefCell<AINode>>,
+    pv: Vec<PvStep>,
+    root_scores: Vec<(Direction, f64)>,
+    stats: SearchStats,
 }
 
 impl MinMaxResult {
-    fn new(score: f64, node: Rc<RefCell<AINode>>) -> MinMaxResult {
-        MinMaxResult { score, node }
+    fn new(score: f64) -> MinMaxResult {
+        MinMaxResult {
+            score,
+            pv: Vec::new(),
+            root_scores: Vec::new(),
+            stats: SearchStats::default(),
+        }
+    }
+
+    /// Result of a search that isn't minimax, `pv` starts with the chosen move.
+    pub fn from_parts(
+        score: f64,
+        pv: Vec<PvStep>,
+        root_scores: Vec<(Direction, f64)>,
+        stats: SearchStats,
+    ) -> MinMaxResult {
+        MinMaxResult {
+            score,
+            pv,
+            root_scores,
+            stats,
+        }
+    }
+
+    fn after(mut self, step: Option<PvStep>) -> MinMaxResult {
+        if let Some(step) = step {
+            self.pv.insert(0, step);
+        }
+        self
     }
 
-    pub fn get_direction(&self) -> Direction {
-        match self.node.borrow().player {
-            Player::Max(dir) => dir,
-            Player::Min => Direction::UP,
+    /// `None` when the searched position had no legal moves.
+    pub fn get_direction(&self) -> Option<Direction> {
+        match self.pv.first() {
+            Some(PvStep::Move(dir)) => Some(*dir),
+            _ => None,
         }
     }
 
     pub fn get_score(&self) -> f64 {
         self.score
     }
+
+    /// Expected line of play, alternating moves and the spawns the search expects in reply.
+    pub fn principal_variation(&self) -> &[PvStep] {
+        &self.pv
+    }
+
+    /// Backed-up score of every legal move at the root.
+    pub fn root_scores(&self) -> &[(Direction, f64)] {
+        &self.root_scores
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.stats.nodes()
+    }
+
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+}
+
+/// A search that keeps its tree between moves, implemented by both `AI` and `Mcts` so the
+/// front ends can swap them.
+pub trait Searcher {
+    fn think(&mut self) -> MinMaxResult;
+    fn set_board(&mut self, board: &Board);
+    /// Plays `dir` on the stored position, `spawn` is the cell the tile landed on and the tile.
+    fn advance(&mut self, dir: Direction, spawn: Option<((usize, usize), u32)>) -> bool;
+    /// The tile the coming spawn places, searches that can use it leave out the other tiles.
+    fn set_next_tile(&mut self, _tile: u32) {}
+    fn export_dot(&self, path: &Path, max_depth: u32) -> io::Result<()>;
 }
 
 pub trait AIScore {
@@ -123,14 +292,15 @@ impl AIScore for Board {
                     if cell > max {
                         max = cell;
                     }
-                    if (x == 0 && y == 0)
-                        || (x == 0 && y == self.height() - 1)
-                        || (x == self.width() - 1 && y == 0)
-                        || (x == self.width() - 1 && y == self.height() - 1)
-                    {
-                        score += 2i32.pow((1.25 * cell) as u32) as f32;
-                    } else if x == 0 || x == self.width() - 1 || y == 0 || y == self.height() - 1 {
-                        score += 2i32.pow((1.10 * cell) as u32) as f32;
+                    // Blocks hold tiles in place like the sides of the board do.
+                    let wall =
+                        |x: usize, y: usize| self.cell(x, y).map_or(true, |c| c.is_blocked());
+                    let horizontal = x == 0 || wall(x - 1, y) || wall(x + 1, y);
+                    let vertical = y == 0 || wall(x, y - 1) || wall(x, y + 1);
+                    if horizontal && vertical {
+                        score += 2f32.powi((1.25 * cell) as i32);
+                    } else if horizontal || vertical {
+                        score += 2f32.powi((1.10 * cell) as i32);
                     }
                 }
             }
@@ -141,10 +311,81 @@ impl AIScore for Board {
     }
 }
 
+fn write_dot_node<W: Write>(
+    out: &mut W,
+    nodes: &[AINode],
+    id: NodeId,
+    layer: u32,
+    pruned: bool,
+    max_depth: u32,
+) -> io::Result<()> {
+    let node = &nodes[id];
+    let (shape, kind) = match node.player {
+        Player::Min => ("box", "move"),
+        Player::Max(_) => ("ellipse", "spawn"),
+    };
+    let value = match node.value {
+        Some(value) => value.to_string(),
+        None => String::from("-"),
+    };
+    let board = node.board.to_string().replace('\n', "\\l");
+    let style = if pruned {
+        ", style=dashed, color=red"
+    } else {
+        ""
+    };
+    writeln!(
+        out,
+        "    n{} [shape={}, label=\"{}\\l{}\\lh={} v={}\\l\"{}];",
+        id,
+        shape,
+        kind,
+        board,
+        node.board.get_ai_score(),
+        value,
+        style
+    )?;
+
+    if layer < max_depth {
+        if let Some(options) = node.options.as_ref() {
+            for &child_id in options.iter() {
+                let child = &nodes[child_id];
+                let child_pruned = pruned || (node.value.is_some() && child.value.is_none());
+                write_dot_node(out, nodes, child_id, layer + 1, child_pruned, max_depth)?;
+                let label = child.step().map_or(String::new(), |step| step.to_string());
+                writeln!(
+                    out,
+                    "    n{} -> n{} [label=\"{}\"{}];",
+                    id,
+                    child_id,
+                    label,
+                    if child_pruned {
+                        ", style=dashed, color=red"
+                    } else {
+                        ""
+                    }
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The search tree lives in a single arena, nodes refer to their children by index.
 pub struct AI {
     board: Board,
     depth: u32,
-    root: Option<Rc<RefCell<AINode>>>,
+    nodes: Vec<AINode>,
+    root: Option<NodeId>,
+    stats: SearchStats,
+    evaluations: HashMap<Board, f64>,
+    reused: usize,
+    heuristic: Option<Box<Fn(&Board) -> f64>>,
+    /// The tile the spawn after the move from the root places, when known.
+    next_tile: Option<u32>,
+    /// Once passed the running search gives up, its result is then meaningless.
+    deadline: Option<Instant>,
+    aborted: bool,
 }
 
 impl AI {
@@ -152,101 +393,392 @@ impl AI {
         AI {
             board: board.clone(),
             depth,
+            nodes: Vec::new(),
             root: None,
+            stats: SearchStats::default(),
+            evaluations: HashMap::new(),
+            reused: 0,
+            heuristic: None,
+            next_tile: None,
+            deadline: None,
+            aborted: false,
         }
     }
 
-    pub fn build_tree(&mut self) {
-        let mut root = AINode::new(self.board.clone(), 0, Player::Min);
-        root.build_tree(1);
-        self.root = Some(Rc::new(RefCell::new(root)));
+    /// Evaluator used by searches that aren't passed one, instead of `get_ai_score`.
+    pub fn set_heuristic(&mut self, heuristic: Box<Fn(&Board) -> f64>) {
+        self.heuristic = Some(heuristic);
+    }
+
+    /// Iterative deepening up to `depth`. Once `time_limit` has passed the running iteration is
+    /// abandoned, only the first one always completes. Returns the result of the deepest
+    /// completed search and its depth.
+    pub fn search(board: &Board, depth: u32, time_limit: Option<Duration>) -> (MinMaxResult, u32) {
+        let start = Instant::now();
+        let mut ai = AI::new(board, 1);
+        ai.build_tree();
+        let mut stats = SearchStats::default();
+        let mut completed: Option<MinMaxResult> = None;
+        loop {
+            let mut result = ai.minimax(None);
+            stats.merge(&result.stats);
+            if ai.aborted {
+                if let Some(mut completed) = completed {
+                    completed.stats = stats;
+                    return (completed, ai.depth - 1);
+                }
+            }
+            let expired = time_limit.map_or(false, |limit| start.elapsed() >= limit);
+            if ai.depth >= depth || expired {
+                result.stats = stats;
+                return (result, ai.depth);
+            }
+            completed = Some(result);
+            ai.depth += 1;
+            ai.deadline = time_limit.map(|limit| start + limit);
+        }
     }
 
-    pub fn minimax(&self, heuristic: Option<Box<Fn(&Board) -> f64>>) -> MinMaxResult {
-        let heuristic = match heuristic {
-            Some(h) => Some(Rc::new(RefCell::new(h))),
+    /// Index among the spawn cells of the tile that leaves the player worst off `depth` plies
+    /// later, for a `board` that has just slid in `dir`. `None` when no spawn cell is free.
+    pub fn worst_spawn(board: &Board, dir: Direction, depth: u32) -> Option<usize> {
+        let mut ai = AI::new(board, depth);
+        ai.nodes.push(AINode::new(board.clone(), Player::Max(dir)));
+        ai.root = Some(0);
+        let result = ai.minimaxfn(0, 0, depth.max(1), None, None, None);
+        match result.principal_variation().first() {
+            Some(&PvStep::Spawn(x, y, _)) => board
+                .free_spawns(dir)
+                .into_iter()
+                .find(|&index| board.spawn_cell(dir, index) == (x, y)),
             _ => None,
+        }
+    }
+
+    pub fn build_tree(&mut self) {
+        self.reused = 0;
+        self.next_tile = None;
+        self.nodes.clear();
+        self.nodes
+            .push(AINode::new(self.board.clone(), Player::Min));
+        self.root = Some(0);
+        self.add_layer(0, 0);
+    }
+
+    /// Spawns right after the coming move only place `tile`, replies with any other tile the
+    /// tree already holds are dropped. Holds until the root moves.
+    pub fn set_next_tile(&mut self, tile: u32) {
+        self.next_tile = Some(tile);
+        let root = match self.root {
+            Some(root) => root,
+            None => return,
         };
-        self.minimaxfn(
-            Rc::clone(self.root.as_ref().unwrap()),
-            0,
-            self.depth,
-            None,
-            None,
-            heuristic,
-        )
+        for child in self.nodes[root].options.clone().into_iter().flatten() {
+            if let Some(options) = self.nodes[child].options.take() {
+                let nodes = &self.nodes;
+                let kept = options
+                    .into_iter()
+                    .filter(|&grandchild| nodes[grandchild].spawned() == Some(tile))
+                    .collect();
+                self.nodes[child].options = Some(kept);
+            }
+        }
+    }
+
+    /// Moves the root to `board`. When `board` is a position two plies below the current root
+    /// the subtree already built under it is kept, otherwise the tree is rebuilt.
+    pub fn set_board(&mut self, board: &Board) {
+        if self.root.is_some() && self.board == *board {
+            return;
+        }
+        self.board = board.clone();
+        let found = self.root.and_then(|root| {
+            self.nodes[root]
+                .options
+                .iter()
+                .flatten()
+                .filter_map(|&child| self.nodes[child].options.as_ref())
+                .flatten()
+                .cloned()
+                .find(|&grandchild| self.nodes[grandchild].board == *board)
+        });
+        match found {
+            Some(id) => self.reroot(id),
+            None => self.build_tree(),
+        }
+    }
+
+    /// Moves the root to the position after playing `dir` and the tile of `spawn` landing on
+    /// its cell, keeping the subtree the last search built for that reply. Returns whether
+    /// anything could be reused, the tree is rebuilt from the new position otherwise.
+    pub fn advance(&mut self, dir: Direction, spawn: Option<((usize, usize), u32)>) -> bool {
+        let found = self.root.and_then(|root| {
+            let moved =
+                self.nodes[root]
+                    .options
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .find(|&child| match self.nodes[child].player {
+                        Player::Max(d) => d == dir,
+                        Player::Min => false,
+                    })?;
+            self.nodes[moved]
+                .options
+                .iter()
+                .flatten()
+                .cloned()
+                .find(|&grandchild| {
+                    let node = &self.nodes[grandchild];
+                    spawn.map_or(false, |(cell, tile)| {
+                        node.spawn == Some(cell) && node.spawned() == Some(tile)
+                    })
+                })
+        });
+        match found {
+            Some(id) => {
+                self.reroot(id);
+                true
+            }
+            None => {
+                let mut board = self.board.clone();
+                board.step_rows(dir);
+                if let Some(((x, y), tile)) = spawn {
+                    board.get_mut_cell(x, y).set_score(tile);
+                }
+                self.board = board;
+                self.build_tree();
+                false
+            }
+        }
     }
 
-    pub fn minimaxfn(
-        &self,
-        node: Rc<RefCell<AINode>>,
+    /// Compacts the arena down to the subtree under `id`, which becomes the new root.
+    fn reroot(&mut self, id: NodeId) {
+        let mut mapping = vec![None; self.nodes.len()];
+        let mut order = vec![id];
+        mapping[id] = Some(0);
+        let mut i = 0;
+        while i < order.len() {
+            for &child in self.nodes[order[i]].options.iter().flatten() {
+                mapping[child] = Some(order.len());
+                order.push(child);
+            }
+            i += 1;
+        }
+
+        let mut old: Vec<Option<AINode>> = self.nodes.drain(..).map(Some).collect();
+        for &old_id in order.iter() {
+            let mut node = old[old_id].take().unwrap();
+            node.value = None;
+            if let Some(options) = node.options.as_mut() {
+                for child in options.iter_mut() {
+                    *child = mapping[*child].unwrap();
+                }
+            }
+            self.nodes.push(node);
+        }
+        self.nodes[0].spawn = None;
+        self.board = self.nodes[0].board.clone();
+        self.root = Some(0);
+        self.reused = self.nodes.len();
+        self.next_tile = None;
+    }
+
+    /// Adds the children of `id`, which is `layer` plies below the root.
+    fn add_layer(&mut self, id: NodeId, layer: u32) {
+        let tile = if layer == 1 { self.next_tile } else { None };
+        let children = self.nodes[id].children(tile);
+        if children.is_empty() {
+            return;
+        }
+        let start = self.nodes.len();
+        self.stats.nodes_created += children.len();
+        self.nodes.extend(children);
+        self.nodes[id].options = Some((start..self.nodes.len()).collect());
+    }
+
+    /// Bytes held by the arena, including the boards and child lists of every node.
+    fn tree_memory(&self) -> usize {
+        let cells = self.board.width() * self.board.height();
+        self.nodes.capacity() * mem::size_of::<AINode>()
+            + self.nodes.len() * (cells * mem::size_of::<Cell>() + mem::size_of::<NodeId>())
+    }
+
+    pub fn minimax(&mut self, heuristic: Option<Box<Fn(&Board) -> f64>>) -> MinMaxResult {
+        // The previous search took its stats with it, anything counted since then was created
+        // by `build_tree` or `advance` for this one.
+        self.stats.nodes_reused = self.reused;
+        self.reused = 0;
+        self.aborted = false;
+        self.evaluations.clear();
+        // Values left by earlier searches would hide what this one pruned.
+        for node in self.nodes.iter_mut() {
+            node.value = None;
+        }
+        let own = heuristic.is_none();
+        let heuristic = heuristic.or_else(|| self.heuristic.take());
+        let mut best = self.minimax_root(heuristic.as_ref());
+        if own {
+            self.heuristic = heuristic;
+        }
+        let mut stats = mem::replace(&mut self.stats, SearchStats::default());
+        stats.searches = 1;
+        stats.tree_nodes = self.nodes.len();
+        stats.peak_memory = self.tree_memory();
+        best.stats = stats;
+        best
+    }
+
+    fn minimax_root(&mut self, heuristic: Option<&Box<Fn(&Board) -> f64>>) -> MinMaxResult {
+        let root = self.root.unwrap();
+        let options = match self.nodes[root].options.clone() {
+            Some(options) => options,
+            None => return self.minimaxfn(root, 0, self.depth, None, None, heuristic),
+        };
+        self.stats.visit(0);
+
+        // Root moves are searched with an open window so every move gets an exact score.
+        let mut best: Option<MinMaxResult> = None;
+        let mut root_scores = Vec::new();
+        for child in options {
+            let value = self.minimaxfn(child, 1, self.depth, None, None, heuristic);
+            if self.aborted {
+                return value;
+            }
+            if let Player::Max(dir) = self.nodes[child].player {
+                root_scores.push((dir, value.score));
+            }
+            if best.is_none() || value.score > best.as_ref().unwrap().score {
+                best = Some(value.after(self.nodes[child].step()));
+            }
+        }
+        let mut best = best.unwrap();
+        best.root_scores = root_scores;
+        self.nodes[root].value = Some(best.score);
+        best
+    }
+
+    /// Writes the tree up to `max_depth` layers as a Graphviz digraph. Boxes are positions with
+    /// the player to move, ellipses positions waiting for a spawn. Branches the last `minimax`
+    /// call pruned are drawn dashed and red.
+    pub fn export_dot<P: AsRef<Path>>(&self, path: P, max_depth: u32) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "digraph search {{")?;
+        writeln!(out, "    node [fontname=\"monospace\"];")?;
+        if let Some(root) = self.root {
+            write_dot_node(&mut out, &self.nodes, root, 0, false, max_depth)?;
+        }
+        writeln!(out, "}}")?;
+        out.flush()
+    }
+
+    /// Heuristic score of the board at `id`, positions reached through different move orders
+    /// are usually only evaluated once per search.
+    fn evaluate(&mut self, id: NodeId) -> f64 {
+        let board = &self.nodes[id].board;
+        if let Some(&score) = self.evaluations.get(board) {
+            self.stats.transposition_hits += 1;
+            return score;
+        }
+        let score = board.get_ai_score();
+        if self.evaluations.len() >= TRANSPOSITION_CAPACITY {
+            self.evaluations.clear();
+        }
+        self.evaluations.insert(board.clone(), score);
+        score
+    }
+
+    fn minimaxfn(
+        &mut self,
+        node: NodeId,
         layer: u32,
         depth: u32,
         alpha: Option<f64>,
         beta: Option<f64>,
-        heuristic: Option<Rc<RefCell<Box<Fn(&Board) -> f64>>>>,
+        heuristic: Option<&Box<Fn(&Board) -> f64>>,
     ) -> MinMaxResult {
         let mut alpha = alpha;
         let mut beta = beta;
-        if node.borrow().layer < depth && node.borrow().options.is_none() {
-            node.borrow_mut().add_layer();
+        if self.aborted
+            || self
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline)
+        {
+            self.aborted = true;
+            return MinMaxResult::new(0.0);
+        }
+        self.stats.visit(layer);
+        if layer < depth && self.nodes[node].options.is_none() {
+            self.add_layer(node, layer);
         }
-        if node.borrow().options.is_none() {
-            if heuristic.is_some() {
-                let score = (heuristic.unwrap().borrow())(&node.borrow().board);
-                MinMaxResult::new(score, Rc::clone(&node))
-            } else {
-                MinMaxResult::new(node.borrow().board.get_ai_score(), Rc::clone(&node))
+        let count = self.nodes[node]
+            .options
+            .as_ref()
+            .map_or(0, |options| options.len());
+        let result = if count == 0 {
+            self.stats.leaf_evaluations += 1;
+            match heuristic {
+                Some(heuristic) => MinMaxResult::new(heuristic(&self.nodes[node].board)),
+                None => MinMaxResult::new(self.evaluate(node)),
             }
         } else {
-            match node.borrow().player {
-                Player::Min => {
-                    let mut max: Option<MinMaxResult> = None;
-                    for child in node.borrow().options.as_ref().unwrap().iter() {
-                        let value = self.minimaxfn(
-                            Rc::clone(child),
-                            layer + 1,
-                            depth,
-                            alpha,
-                            beta,
-                            heuristic.clone(),
-                        );
-                        if max.is_none() || value.score > max.as_ref().unwrap().score {
-                            max = Some(MinMaxResult::new(value.score, Rc::clone(child)));
-                        }
-                        if alpha.is_none() || value.score > alpha.unwrap() {
-                            alpha = Some(value.score);
-                        }
-                        if alpha.is_some() && beta.is_some() && alpha.unwrap() > beta.unwrap() {
-                            break;
-                        }
-                    }
-                    max.unwrap()
+            let maximize = match self.nodes[node].player {
+                Player::Min => true,
+                Player::Max(_) => false,
+            };
+            let mut best: Option<MinMaxResult> = None;
+            for i in 0..count {
+                let child = self.nodes[node].options.as_ref().unwrap()[i];
+                let value = self.minimaxfn(child, layer + 1, depth, alpha, beta, heuristic);
+                if self.aborted {
+                    return value;
                 }
-                Player::Max(_) => {
-                    let mut max: Option<MinMaxResult> = None;
-                    for child in node.borrow().options.as_ref().unwrap().iter() {
-                        let value = self.minimaxfn(
-                            Rc::clone(child),
-                            layer + 1,
-                            depth,
-                            alpha,
-                            beta,
-                            heuristic.clone(),
-                        );
-                        if max.is_none() || value.score < max.as_ref().unwrap().score {
-                            max = Some(MinMaxResult::new(value.score, Rc::clone(child)));
-                        }
-                        if beta.is_none() || value.score < beta.unwrap() {
-                            beta = Some(value.score);
-                        }
-                        if alpha.is_some() && beta.is_some() && alpha.unwrap() > beta.unwrap() {
-                            break;
-                        }
-                    }
-                    max.unwrap()
+                let score = value.score;
+                let better = match best.as_ref() {
+                    None => true,
+                    Some(best) if maximize => score > best.score,
+                    Some(best) => score < best.score,
+                };
+                if better {
+                    best = Some(value.after(self.nodes[child].step()));
+                }
+                if maximize && (alpha.is_none() || score > alpha.unwrap()) {
+                    alpha = Some(score);
+                }
+                if !maximize && (beta.is_none() || score < beta.unwrap()) {
+                    beta = Some(score);
+                }
+                if alpha.is_some() && beta.is_some() && alpha.unwrap() > beta.unwrap() {
+                    self.stats.cutoffs += 1;
+                    break;
                 }
             }
-        }
+            best.unwrap()
+        };
+        self.nodes[node].value = Some(result.score);
+        result
+    }
+}
+
+impl Searcher for AI {
+    fn think(&mut self) -> MinMaxResult {
+        self.minimax(None)
+    }
+
+    fn set_board(&mut self, board: &Board) {
+        AI::set_board(self, board)
+    }
+
+    fn advance(&mut self, dir: Direction, spawn: Option<((usize, usize), u32)>) -> bool {
+        AI::advance(self, dir, spawn)
+    }
+
+    fn set_next_tile(&mut self, tile: u32) {
+        AI::set_next_tile(self, tile)
+    }
+
+    fn export_dot(&self, path: &Path, max_depth: u32) -> io::Result<()> {
+        AI::export_dot(self, path, max_depth)
     }
 }
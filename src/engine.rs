@@ -0,0 +1,360 @@
+use serde_json::Value;
+
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+use crate::agent;
+use crate::ai::AIScore;
+use crate::ai::{MinMaxResult, AI};
+use crate::game::{Board, Direction, Game, GameStatus, Notation};
+use crate::rules::{self, Ruleset};
+
+const DEFAULT_DEPTH: u32 = 6;
+const MAX_TIMED_DEPTH: u32 = 12;
+/// Longest a search may run, deep searches on big rulesets would otherwise take minutes and
+/// gigabytes. Searches given only a depth stop here too.
+const MAX_TIME_MS: u64 = 5_000;
+/// Largest width and height accepted by `set_board`, searches on bigger boards never finish.
+pub const MAX_BOARD_SIZE: usize = 16;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    New {
+        target: Option<u32>,
+        endless: Option<bool>,
+        rules: Option<String>,
+        blocks: Option<String>,
+    },
+    SetBoard {
+        board: Vec<Vec<u32>>,
+        blocks: Option<String>,
+    },
+    Move {
+        direction: String,
+    },
+    Legal,
+    Best {
+        depth: Option<u32>,
+        time_ms: Option<u64>,
+        agent: Option<String>,
+    },
+    Eval,
+    State,
+    Quit,
+}
+
+pub fn status_name(status: GameStatus) -> &'static str {
+    match status {
+        GameStatus::Playing => "playing",
+        GameStatus::Won => "won",
+        GameStatus::Lost => "lost",
+    }
+}
+
+pub fn board_json(board: &Board) -> Value {
+    let rows: Vec<Vec<u64>> = (0..board.height())
+        .map(|y| {
+            (0..board.width())
+                .map(|x| match board.get_cell(x, y).get_score() {
+                    Some(score) => board.rules().tile_value(score),
+                    None => 0,
+                })
+                .collect()
+        })
+        .collect();
+    json!(rows)
+}
+
+pub fn state_json(game: &Game) -> Value {
+    json!({
+        "board": board_json(game.get_board()),
+        "score": game.get_score(),
+        "merge_score": game.merge_score(),
+        "moves": game.moves(),
+        "status": status_name(game.status()),
+        "target": game.rules().tile_value(game.target()),
+        "rules": game.rules().name(),
+        "next": if game.rules().previews_next_tile() {
+            json!(game.rules().tile_value(game.next_tile()))
+        } else {
+            Value::Null
+        },
+        "endless": game.is_endless(),
+        "reached_target": game.reached_target(),
+        "blocked": game.get_board().blocked_cells(),
+    })
+}
+
+/// Converts rows of tile values (0 for empty) into a board played under `rules`, with the cells
+/// at `blocks` blocked. Blocked cells must be given as 0.
+pub fn parse_board(
+    rows: &[Vec<u32>],
+    rules: &'static dyn Ruleset,
+    blocks: &[(usize, usize)],
+) -> Result<Board, String> {
+    if rows.len() > MAX_BOARD_SIZE || rows.iter().any(|row| row.len() > MAX_BOARD_SIZE) {
+        return Err(format!(
+            "boards are at most {} by {} cells",
+            MAX_BOARD_SIZE, MAX_BOARD_SIZE
+        ));
+    }
+    let mut exponents = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut cells = Vec::with_capacity(row.len());
+        for &value in row {
+            cells.push(match value {
+                0 => None,
+                v => match rules.tile_code(v as u64) {
+                    Some(tile) => Some(tile),
+                    None => return Err(format!("{} is not a valid tile", v)),
+                },
+            });
+        }
+        exponents.push(cells);
+    }
+    let mut board = Board::from_exponents(&exponents)?.with_rules(rules);
+    for &(x, y) in blocks {
+        if x >= board.width() || y >= board.height() {
+            return Err(format!("block {},{} is off the board", x, y));
+        }
+        if board.get_cell(x, y).is_set() {
+            return Err(format!("block {},{} holds a tile", x, y));
+        }
+        board.get_mut_cell(x, y).set_blocked();
+    }
+    Ok(board)
+}
+
+pub fn parse_target(target: u32, rules: &dyn Ruleset) -> Result<u32, String> {
+    rules.tile_code(target as u64).ok_or_else(|| {
+        format!(
+            "target {} is not a tile under the {} rules",
+            target,
+            rules.name()
+        )
+    })
+}
+
+/// Blocked cells of a layout written as a compact board with `#` for blocks, like
+/// `..../.#../..#./....`.
+pub fn parse_blocks(layout: &str) -> Result<Vec<(usize, usize)>, String> {
+    Ok(Board::parse(layout, Notation::Compact)?.blocked_cells())
+}
+
+/// Starts a game under the named rules, `target` is a tile value and defaults to the one the
+/// rules pick.
+pub fn new_game(
+    rules: Option<&str>,
+    target: Option<u32>,
+    endless: bool,
+    blocks: &[(usize, usize)],
+) -> Result<Game, String> {
+    let rules = rules::by_name(rules.unwrap_or("edge"))?;
+    let target = match target {
+        Some(target) => parse_target(target, rules)?,
+        None => rules.default_target(),
+    };
+    Game::with_blocks(rules, target, endless, blocks)
+}
+
+fn search_json(result: &MinMaxResult) -> Value {
+    let pv: Vec<String> = result
+        .principal_variation()
+        .iter()
+        .map(|step| step.to_string())
+        .collect();
+    let mut scores = serde_json::Map::new();
+    for (dir, score) in result.root_scores() {
+        scores.insert(dir.to_string(), json!(score));
+    }
+    json!({
+        "score": result.get_score(),
+        "pv": pv,
+        "scores": scores,
+        "nodes": result.nodes(),
+        "stats": result.stats(),
+    })
+}
+
+/// Runs the AI on the game, searching to `depth` or iteratively deepening until `time_ms` has
+/// passed, but never for longer than `MAX_TIME_MS`. Any other agent can be picked by name
+/// instead, it then runs with its default settings and neither `depth` nor `time_ms` may be
+/// given. The learned agent is refused, it would train for every request.
+pub fn suggest_json(
+    game: &Game,
+    depth: Option<u32>,
+    time_ms: Option<u64>,
+    agent: Option<&str>,
+) -> Result<Value, String> {
+    let board = game.get_board();
+    if !board.has_moves() {
+        return Err("no legal moves".to_string());
+    }
+    if let Some(name) = agent {
+        if depth.is_some() || time_ms.is_some() {
+            return Err("depth and time_ms can't be combined with an agent".to_string());
+        }
+        if name == "learned" {
+            return Err(
+                "the learned agent trains before it plays, use the command line".to_string(),
+            );
+        }
+        let mut agent = agent::by_name(name, board)?;
+        let dir = agent.choose_move(game).ok_or("no legal moves")?;
+        let mut value = match agent.last_search() {
+            Some(search) => search_json(search),
+            None => json!({}),
+        };
+        value["direction"] = json!(dir.to_string());
+        value["agent"] = json!(agent.name());
+        return Ok(value);
+    }
+
+    let depth = match (depth, time_ms) {
+        (Some(depth), _) if depth > MAX_TIMED_DEPTH => {
+            return Err(format!(
+                "depth {} is above the limit of {}",
+                depth, MAX_TIMED_DEPTH
+            ));
+        }
+        (Some(depth), _) => depth.max(1),
+        (None, Some(_)) => MAX_TIMED_DEPTH,
+        (None, None) => DEFAULT_DEPTH,
+    };
+    let time_ms = match time_ms {
+        Some(time_ms) if time_ms > MAX_TIME_MS => {
+            return Err(format!(
+                "time_ms {} is above the limit of {}",
+                time_ms, MAX_TIME_MS
+            ));
+        }
+        Some(time_ms) => time_ms,
+        None => MAX_TIME_MS,
+    };
+    let (result, searched) = AI::search(board, depth, Some(Duration::from_millis(time_ms)));
+    let dir = result.get_direction().ok_or("no legal moves")?;
+    let mut value = search_json(&result);
+    value["direction"] = json!(dir.to_string());
+    value["depth"] = json!(searched);
+    Ok(value)
+}
+
+pub struct Engine {
+    game: Game,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine { game: Game::new() }
+    }
+
+    pub fn run() -> io::Result<()> {
+        let mut engine = Engine::new();
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        for line in stdin.lock().lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (response, quit) = match serde_json::from_str::<Request>(&line) {
+                Ok(Request::Quit) => (json!({ "ok": true }), true),
+                Ok(request) => (engine.respond(request), false),
+                Err(e) => (json!({ "ok": false, "error": e.to_string() }), false),
+            };
+            writeln!(out, "{}", response)?;
+            out.flush()?;
+            if quit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn respond(&mut self, request: Request) -> Value {
+        match self.handle(request) {
+            Ok(mut value) => {
+                value["ok"] = json!(true);
+                value
+            }
+            Err(e) => json!({ "ok": false, "error": e }),
+        }
+    }
+
+    pub fn handle(&mut self, request: Request) -> Result<Value, String> {
+        match request {
+            Request::New {
+                target,
+                endless,
+                rules,
+                blocks,
+            } => {
+                // Without new rules the game keeps its rules and target, without a layout its
+                // blocks.
+                let endless = endless.unwrap_or(false);
+                let blocks = match blocks {
+                    Some(layout) => parse_blocks(&layout)?,
+                    None => self.game.get_board().blocked_cells(),
+                };
+                self.game = match rules {
+                    Some(rules) => new_game(Some(&rules), target, endless, &blocks)?,
+                    None => {
+                        let rules = self.game.rules();
+                        let target = match target {
+                            Some(target) => parse_target(target, rules)?,
+                            None => self.game.target(),
+                        };
+                        Game::with_blocks(rules, target, endless, &blocks)?
+                    }
+                };
+                Ok(state_json(&self.game))
+            }
+            Request::SetBoard { board, blocks } => {
+                // Without a layout the game keeps its blocks.
+                let blocks = match blocks {
+                    Some(layout) => parse_blocks(&layout)?,
+                    None => self.game.get_board().blocked_cells(),
+                };
+                let board = parse_board(&board, self.game.rules(), &blocks)?;
+                self.game.set_board(board);
+                Ok(state_json(&self.game))
+            }
+            Request::Move { direction } => {
+                let dir: Direction = direction.parse()?;
+                if !self.game.step(dir) {
+                    return Err(format!("illegal move {}", dir));
+                }
+                Ok(state_json(&self.game))
+            }
+            Request::Legal => {
+                let moves: Vec<String> = self
+                    .game
+                    .get_board()
+                    .legal_moves()
+                    .iter()
+                    .map(|dir| dir.to_string())
+                    .collect();
+                Ok(json!({ "moves": moves }))
+            }
+            Request::Best {
+                depth,
+                time_ms,
+                agent,
+            } => suggest_json(
+                &self.game,
+                depth,
+                time_ms,
+                agent.as_ref().map(|s| s.as_str()),
+            ),
+            Request::Eval => Ok(json!({
+                "heuristic": self.game.get_board().get_ai_score(),
+                "score": self.game.get_score(),
+            })),
+            Request::State => Ok(state_json(&self.game)),
+            Request::Quit => Ok(json!({})),
+        }
+    }
+}
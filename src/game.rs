@@ -1,4 +1,14 @@
-use rand::{prelude::SliceRandom, thread_rng, Rng};
+use rand::{
+    prelude::{IteratorRandom, SliceRandom},
+    thread_rng,
+};
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use crate::rules::{EdgeSpawn, Ruleset};
+use crate::spawner::Spawner;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Direction {
@@ -8,22 +18,132 @@ pub enum Direction {
     RIGHT,
 }
 
-#[derive(Debug)]
+impl Direction {
+    pub fn all() -> [Direction; 4] {
+        [
+            Direction::UP,
+            Direction::DOWN,
+            Direction::LEFT,
+            Direction::RIGHT,
+        ]
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Direction::UP => "up",
+            Direction::DOWN => "down",
+            Direction::LEFT => "left",
+            Direction::RIGHT => "right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Direction, String> {
+        match s.to_lowercase().as_str() {
+            "up" | "u" => Ok(Direction::UP),
+            "down" | "d" => Ok(Direction::DOWN),
+            "left" | "l" => Ok(Direction::LEFT),
+            "right" | "r" => Ok(Direction::RIGHT),
+            _ => Err(format!("unknown direction '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GameStatus {
+    Playing,
+    Won,
+    Lost,
+}
+
+#[derive(Debug, Clone)]
 pub struct Game {
     scoreTarget: u32,
+    endless: bool,
     moves: u32,
     board: Board,
-    lost: bool,
+    status: GameStatus,
+    reached_target: Option<u32>,
+    milestones: Vec<(u32, u32)>,
+    last_spawn: Option<(usize, usize)>,
+    pending_spawn: Option<Direction>,
+    deck: Vec<u32>,
+    next_tile: u32,
+    merge_score: u64,
 }
 
 impl Game {
     pub fn new() -> Game {
-        Game {
-            scoreTarget: 11,
-            moves: 0,
-            board: Board::new(),
-            lost: false,
+        Game::with_target(11, false)
+    }
+
+    /// In endless mode reaching `target` is only recorded and play continues until no moves remain.
+    pub fn with_target(target: u32, endless: bool) -> Game {
+        Game::with_rules(&EdgeSpawn, target, endless)
+    }
+
+    pub fn with_rules(rules: &'static dyn Ruleset, target: u32, endless: bool) -> Game {
+        Game::with_board(Board::empty(4, 4, rules), target, endless)
+    }
+
+    /// Like `with_rules` with the cells at `blocks` blocked before the first tiles are placed.
+    pub fn with_blocks(
+        rules: &'static dyn Ruleset,
+        target: u32,
+        endless: bool,
+        blocks: &[(usize, usize)],
+    ) -> Result<Game, String> {
+        let mut board = Board::empty(4, 4, rules);
+        for &(x, y) in blocks {
+            if x >= board.width() || y >= board.height() {
+                return Err(format!("block {},{} is off the board", x, y));
+            }
+            board.get_mut_cell(x, y).set_blocked();
         }
+        if board.data.iter().all(|cell| cell.is_blocked()) {
+            return Err("every cell is blocked".to_string());
+        }
+        Ok(Game::with_board(board, target, endless))
+    }
+
+    /// Starts a game on `board`, which holds no tiles yet.
+    fn with_board(mut board: Board, target: u32, endless: bool) -> Game {
+        let rules = board.rules();
+        let mut deck = Vec::new();
+        rules.start(&mut board, &mut deck);
+        let next_tile = rules.draw(&mut deck, &board);
+        let mut game = Game {
+            scoreTarget: target,
+            endless,
+            moves: 0,
+            board,
+            status: GameStatus::Playing,
+            reached_target: None,
+            milestones: Vec::new(),
+            last_spawn: None,
+            pending_spawn: None,
+            deck,
+            next_tile,
+            merge_score: 0,
+        };
+        // Blocks can leave the starting tiles without a move.
+        game.update_status();
+        game
+    }
+
+    pub fn rules(&self) -> &'static dyn Ruleset {
+        self.board.rules()
+    }
+
+    /// The tile the next spawn places.
+    pub fn next_tile(&self) -> u32 {
+        self.next_tile
     }
 
     pub fn get_board(&self) -> &Board {
@@ -34,44 +154,194 @@ impl Game {
         &mut self.board
     }
 
+    /// Plays `dir`, returns `false` if the move was illegal or the game is already over.
     pub fn step(&mut self, dir: Direction) -> bool {
-        self.lost = !self.board.step(dir);
-        self.lost
+        if !self.slide(dir) {
+            return false;
+        }
+        if self.pending_spawn.is_some() {
+            self.spawn_random();
+        }
+        true
     }
 
-    pub fn print_board(&self) {
-        self.board.print_board();
+    /// Plays `dir` with `spawner` picking where the new tile goes, a random free cell is used
+    /// when it picks one that isn't free.
+    pub fn step_with(&mut self, dir: Direction, spawner: &mut dyn Spawner) -> bool {
+        if !self.slide(dir) {
+            return false;
+        }
+        if self.pending_spawn.is_some() {
+            let placed = match spawner.choose_spawn(&self.board, dir) {
+                Some(index) => self.spawn(index),
+                None => false,
+            };
+            if !placed {
+                self.spawn_random();
+            }
+        }
+        true
     }
 
-    pub fn get_score(&self) -> i32 {
-        let mut cells: i32 = 0;
-        let mut score: i32 = 0;
+    /// Slides the board without spawning, the move completes once `spawn` places the tile.
+    /// Moves that leave no free cell to spawn on spawn nothing and complete right away.
+    pub fn slide(&mut self, dir: Direction) -> bool {
+        if self.is_over()
+            || self.pending_spawn.is_some()
+            || !self.board.step_rows_scoring(dir, &mut self.merge_score)
+        {
+            return false;
+        }
+        if self.board.free_spawns(dir).is_empty() {
+            self.finish_step(None);
+        } else {
+            self.pending_spawn = Some(dir);
+        }
+        true
+    }
 
-        for cell in self.board.data.iter() {
-            if cell.is_set() {
-                cells += 1;
-                score += 2_i32.pow(cell.get_score().unwrap());
+    /// Places the tile of a `slide` on the `index`th of the rules' spawn cells, returns `false`
+    /// when no spawn is pending or the cell isn't free.
+    pub fn spawn(&mut self, index: usize) -> bool {
+        if !self.spawn_tile(index, self.next_tile) {
+            return false;
+        }
+        self.next_tile = self.rules().draw(&mut self.deck, &self.board);
+        true
+    }
+
+    /// Like `spawn` but placing `tile` rather than the next tile, for replaying games.
+    pub fn spawn_tile(&mut self, index: usize, tile: u32) -> bool {
+        let dir = match self.pending_spawn {
+            Some(dir) => dir,
+            None => return false,
+        };
+        if !self.board.spawn_at(dir, index, tile) {
+            return false;
+        }
+        let spawn = self.board.spawn_cell(dir, index);
+        self.finish_step(Some(spawn));
+        true
+    }
+
+    fn spawn_random(&mut self) {
+        if let Some(dir) = self.pending_spawn {
+            let free = self.board.free_spawns(dir);
+            if let Some(&index) = free.choose(&mut thread_rng()) {
+                self.spawn(index);
             }
         }
+    }
 
-        score - cells + 1
+    /// Direction of a `slide` still waiting for its tile.
+    pub fn pending_spawn(&self) -> Option<Direction> {
+        self.pending_spawn
     }
 
-    pub fn has_won(&self) -> bool {
-        for cell in self.board.data.iter() {
-            if cell.is_set() && cell.get_score().unwrap() == self.scoreTarget {
-                return true;
+    fn finish_step(&mut self, spawn: Option<(usize, usize)>) {
+        self.pending_spawn = None;
+        self.last_spawn = spawn;
+        self.moves += 1;
+        self.update_milestones();
+        self.update_status();
+    }
+
+    fn update_milestones(&mut self) {
+        if let Some(max) = self.board.max_tile() {
+            if self.milestones.last().map_or(true, |&(tile, _)| max > tile) {
+                self.milestones.push((max, self.moves));
             }
         }
-        false
+        if self.reached_target.is_none() && self.has_won() {
+            self.reached_target = Some(self.moves);
+        }
+    }
+
+    fn update_status(&mut self) {
+        self.status = if self.has_won() && !self.endless {
+            GameStatus::Won
+        } else if self.rules().is_terminal(&self.board) {
+            if self.reached_target.is_some() {
+                GameStatus::Won
+            } else {
+                GameStatus::Lost
+            }
+        } else {
+            GameStatus::Playing
+        };
+    }
+
+    pub fn status(&self) -> GameStatus {
+        self.status
+    }
+
+    pub fn moves(&self) -> u32 {
+        self.moves
+    }
+
+    /// Where the tile after the last move spawned, `None` if there was no free cell for it.
+    pub fn last_spawn(&self) -> Option<(usize, usize)> {
+        self.last_spawn
+    }
+
+    pub fn target(&self) -> u32 {
+        self.scoreTarget
+    }
+
+    pub fn is_endless(&self) -> bool {
+        self.endless
+    }
+
+    /// Move number on which the target tile was first reached.
+    pub fn reached_target(&self) -> Option<u32> {
+        self.reached_target
+    }
+
+    /// Every new highest tile as `(exponent, move)`, in the order they were reached.
+    pub fn milestones(&self) -> &[(u32, u32)] {
+        &self.milestones
+    }
+
+    pub fn print_board(&self) {
+        self.board.print_board();
+    }
+
+    /// The score the rules give the game, for the edge spawn rules the tile sum minus the
+    /// spawned tiles and for the classic rules the merge score.
+    pub fn get_score(&self) -> i32 {
+        self.rules().score(&self.board, self.merge_score)
+    }
+
+    /// The score of the original game, the values of every tile merged so far. Counted from the
+    /// last `set_board`.
+    pub fn merge_score(&self) -> u64 {
+        self.merge_score
+    }
+
+    pub fn has_won(&self) -> bool {
+        self.rules().has_won(&self.board, self.scoreTarget)
     }
 
     pub fn is_over(&self) -> bool {
-        self.lost || self.has_won()
+        self.status != GameStatus::Playing
+    }
+
+    /// Plays on from `board` under its rules, the deck starts over.
+    pub fn set_board(&mut self, board: Board) {
+        self.board = board;
+        self.deck.clear();
+        self.next_tile = self.rules().draw(&mut self.deck, &self.board);
+        self.merge_score = 0;
+        self.pending_spawn = None;
+        self.milestones.clear();
+        self.reached_target = None;
+        self.update_milestones();
+        self.update_status();
     }
 
+    /// Starts over under the same rules and on the same blocks.
     pub fn reset(&mut self) {
-        self.board = Board::new();
+        *self = Game::with_board(self.board.cleared(), self.scoreTarget, self.endless);
     }
 }
 
@@ -80,16 +350,91 @@ pub struct Board {
     height: usize,
     width: usize,
     data: Vec<Cell>,
+    rules: &'static dyn Ruleset,
+}
+
+/// Boards are equal when their cells are and they are played under the same rules.
+impl PartialEq for Board {
+    fn eq(&self, other: &Board) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.data == other.data
+            && self.rules.name() == other.rules.name()
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.height.hash(state);
+        self.data.hash(state);
+    }
 }
 
 impl Board {
-    fn new() -> Board {
-        let data = vec![Cell::new(); 4 * 4];
+    fn empty(width: usize, height: usize, rules: &'static dyn Ruleset) -> Board {
         Board {
-            height: 4,
-            width: 4,
+            height,
+            width,
+            data: vec![Cell::new(); width * height],
+            rules,
+        }
+    }
+
+    pub fn from_exponents(rows: &[Vec<Option<u32>>]) -> Result<Board, String> {
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 || height == 0 {
+            return Err("board must have at least one row and column".to_string());
+        }
+        let mut data = Vec::with_capacity(width * height);
+        for row in rows {
+            if row.len() != width {
+                return Err(format!(
+                    "expected {} cells per row, got {}",
+                    width,
+                    row.len()
+                ));
+            }
+            data.extend(row.iter().map(|&score| Cell {
+                score,
+                blocked: false,
+            }));
+        }
+        Ok(Board {
+            height,
+            width,
             data,
+            rules: &EdgeSpawn,
+        })
+    }
+
+    pub fn rules(&self) -> &'static dyn Ruleset {
+        self.rules
+    }
+
+    /// The same cells played under `rules`.
+    pub fn with_rules(mut self, rules: &'static dyn Ruleset) -> Board {
+        self.rules = rules;
+        self
+    }
+
+    /// An empty board of the same size and rules, keeping the blocked cells.
+    pub fn cleared(&self) -> Board {
+        let mut board = Board::empty(self.width, self.height, self.rules);
+        for (x, y) in self.blocked_cells() {
+            board.get_mut_cell(x, y).set_blocked();
         }
+        board
+    }
+
+    pub fn blocked_cells(&self) -> Vec<(usize, usize)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.get_cell(x, y).is_blocked())
+            .collect()
     }
 
     pub fn width(&self) -> usize {
@@ -100,8 +445,24 @@ impl Board {
         self.height
     }
 
+    /// `None` when `(x, y)` is off the board.
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x < self.width && y < self.height {
+            self.data.get(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Panics when `(x, y)` is off the board, use `cell` for positions that weren't checked.
     pub fn get_cell(&self, x: usize, y: usize) -> &Cell {
-        self.data.get(y * self.width + x).unwrap()
+        match self.cell(x, y) {
+            Some(cell) => cell,
+            None => panic!(
+                "cell ({}, {}) is off the {}x{} board",
+                x, y, self.width, self.height
+            ),
+        }
     }
 
     pub fn get_mut_cell(&mut self, x: usize, y: usize) -> &mut Cell {
@@ -144,64 +505,138 @@ impl Board {
         col.into_boxed_slice()
     }
 
+    /// Slides the board and spawns a tile, returns `false` if nothing moved.
+    /// A move is still legal when no cell is left to spawn on.
     pub fn step(&mut self, dir: Direction) -> bool {
-        self.step_rows(dir);
-        self.step_add(dir)
+        if !self.step_rows(dir) {
+            return false;
+        }
+        self.step_add(dir);
+        true
     }
 
-    pub fn step_rows(&mut self, dir: Direction) -> bool {
-        match dir {
-            Direction::UP | Direction::DOWN => self.step_vertical(dir),
-            Direction::LEFT | Direction::RIGHT => self.step_horizontal(dir),
+    pub fn has_moves(&self) -> bool {
+        Direction::all()
+            .iter()
+            .any(|&dir| self.clone().step_rows(dir))
+    }
+
+    pub fn legal_moves(&self) -> Vec<Direction> {
+        Direction::all()
+            .iter()
+            .cloned()
+            .filter(|&dir| self.clone().step_rows(dir))
+            .collect()
+    }
+
+    pub fn max_tile(&self) -> Option<u32> {
+        self.data.iter().filter_map(|cell| cell.get_score()).max()
+    }
+
+    /// Places `tile` on a random free cell, returns `false` when the board is full.
+    pub fn add_random_tile(&mut self, tile: u32) -> bool {
+        match self
+            .data
+            .iter_mut()
+            .filter(|cell| cell.is_free())
+            .choose(&mut thread_rng())
+        {
+            Some(cell) => {
+                cell.set_score(tile);
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn step_add(&mut self, dir: Direction) -> bool {
+    pub fn step_rows(&mut self, dir: Direction) -> bool {
+        self.step_rows_scoring(dir, &mut 0)
+    }
+
+    /// Like `step_rows`, adding the value of every merged tile to `score`.
+    pub fn step_rows_scoring(&mut self, dir: Direction, score: &mut u64) -> bool {
         match dir {
-            Direction::UP => self.get_mut_row(self.height - 1).add_random_zero(),
-            Direction::DOWN => self.get_mut_row(0).add_random_zero(),
-            Direction::LEFT => self.get_mut_col(self.width - 1).add_random_zero(),
-            Direction::RIGHT => self.get_mut_col(0).add_random_zero(),
+            Direction::UP | Direction::DOWN => self.step_vertical(dir, score),
+            Direction::LEFT | Direction::RIGHT => self.step_horizontal(dir, score),
         }
     }
 
-    pub fn step_add_index(&mut self, dir: Direction, index: usize) -> bool {
-        let row = match dir {
-            Direction::UP => self.get_mut_row(self.height - 1),
-            Direction::DOWN => self.get_mut_row(0),
-            Direction::LEFT => self.get_mut_col(self.width - 1),
-            Direction::RIGHT => self.get_mut_col(0),
-        };
-        if row[index].is_set() {
-            false
-        } else {
-            row[index].set_score(0);
-            true
+    /// Spawns a tile drawn from the rules' spawn chances on a random free spawn cell of `dir`,
+    /// returns its position.
+    pub fn step_add(&mut self, dir: Direction) -> Option<(usize, usize)> {
+        let mut rng = thread_rng();
+        let index = *self.free_spawns(dir).choose(&mut rng)?;
+        let tile = self
+            .rules
+            .spawn_tiles()
+            .choose_weighted(&mut rng, |&(_, chance)| chance)
+            .ok()?
+            .0;
+        self.spawn_at(dir, index, tile);
+        Some(self.spawn_cell(dir, index))
+    }
+
+    /// The cells a spawn after a move in `dir` can land on, see `Ruleset::spawn_count`.
+    pub fn spawn_cells(&self, dir: Direction) -> Vec<(usize, usize)> {
+        (0..self.rules.spawn_count(self, dir))
+            .map(|index| self.spawn_cell(dir, index))
+            .collect()
+    }
+
+    /// Indices of the free cells among the spawn cells of `dir`.
+    pub fn free_spawns(&self, dir: Direction) -> Vec<usize> {
+        (0..self.rules.spawn_count(self, dir))
+            .filter(|&index| {
+                let (x, y) = self.spawn_cell(dir, index);
+                self.get_cell(x, y).is_free()
+            })
+            .collect()
+    }
+
+    /// Position of the `index`th spawn cell of `dir`.
+    pub fn spawn_cell(&self, dir: Direction, index: usize) -> (usize, usize) {
+        self.rules.spawn_cell(self, dir, index)
+    }
+
+    /// Places `tile` on the `index`th spawn cell of `dir`, returns `false` when the cell is
+    /// taken or there is no such cell.
+    pub fn spawn_at(&mut self, dir: Direction, index: usize, tile: u32) -> bool {
+        if index >= self.rules.spawn_count(self, dir) {
+            return false;
+        }
+        let (x, y) = self.spawn_cell(dir, index);
+        let cell = self.get_mut_cell(x, y);
+        if !cell.is_free() {
+            return false;
         }
+        cell.set_score(tile);
+        true
     }
 
-    fn step_vertical(&mut self, dir: Direction) -> bool {
+    fn step_vertical(&mut self, dir: Direction, score: &mut u64) -> bool {
+        let rules = self.rules;
         let mut ok = false;
         for i in 0..self.width {
             let mut col = self.get_mut_col(i);
             if let Direction::DOWN = dir {
                 col.reverse();
             }
-            if col.combine_row() {
+            if slide_segments(rules, &mut col, score) {
                 ok = true;
             }
         }
         ok
     }
 
-    fn step_horizontal(&mut self, dir: Direction) -> bool {
+    fn step_horizontal(&mut self, dir: Direction, score: &mut u64) -> bool {
+        let rules = self.rules;
         let mut ok = false;
         for i in 0..self.height {
             let mut row = self.get_mut_row(i);
             if let Direction::RIGHT = dir {
                 row.reverse();
             }
-            if row.combine_row() {
+            if slide_segments(rules, &mut row, score) {
                 ok = true;
             }
         }
@@ -213,72 +648,263 @@ impl Board {
     }
 
     pub fn print_board(&self) {
-        for y in 0..self.height {
-            for x in 0..self.width {
-                print!("{}", self.get_cell(x, y).as_symbol());
+        println!("{}", self);
+    }
+
+    /// Parses a board written in the given notation, see `to_notation` for the formats.
+    pub fn parse(s: &str, notation: Notation) -> Result<Board, String> {
+        // Blocks parse as empty cells and are put back afterwards.
+        let open = s.replace('#', ".");
+        let (rows, blocks) = match notation {
+            Notation::Compact => {
+                let rows = open
+                    .trim()
+                    .split('/')
+                    .map(|row| row.trim().chars().map(parse_compact_cell).collect())
+                    .collect::<Result<Vec<_>, String>>()?;
+                let blocks: Vec<Vec<bool>> = s
+                    .trim()
+                    .split('/')
+                    .map(|row| row.trim().chars().map(|c| c == '#').collect())
+                    .collect();
+                (rows, blocks)
             }
-            print!("\n");
+            Notation::Exponents | Notation::Values => {
+                let rows = match notation {
+                    Notation::Exponents => parse_grid(&open, parse_exponent_cell)?,
+                    _ => parse_grid(&open, parse_value_cell)?,
+                };
+                let blocks: Vec<Vec<bool>> = s
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| line.split_whitespace().map(|t| t == "#").collect())
+                    .collect();
+                (rows, blocks)
+            }
+        };
+        let mut board = Board::from_exponents(&rows)?;
+        for (y, row) in blocks.iter().enumerate() {
+            for (x, _) in row.iter().enumerate().filter(|&(_, &blocked)| blocked) {
+                board.get_mut_cell(x, y).set_blocked();
+            }
+        }
+        Ok(board)
+    }
+
+    /// `Exponents` is a grid of exponents with `*` for empty cells, `Values` a grid of tile
+    /// values with `.` for empty cells and `Compact` a single line of base 36 exponents with
+    /// `.` for empty cells and `/` between rows. Blocked cells are `#` in all three.
+    pub fn to_notation(&self, notation: Notation) -> String {
+        let symbol = |cell: &Cell| match notation {
+            _ if cell.is_blocked() => String::from("#"),
+            Notation::Exponents => cell.as_symbol(),
+            Notation::Values => match cell.get_score() {
+                Some(score) => self.rules.tile_value(score).to_string(),
+                None => String::from("."),
+            },
+            Notation::Compact => match cell.get_score() {
+                Some(score) => std::char::from_digit(score, 36).unwrap_or('?').to_string(),
+                None => String::from("."),
+            },
+        };
+        let rows: Vec<Vec<String>> = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| symbol(self.get_cell(x, y)))
+                    .collect()
+            })
+            .collect();
+
+        if let Notation::Compact = notation {
+            return rows
+                .iter()
+                .map(|row| row.concat())
+                .collect::<Vec<_>>()
+                .join("/");
         }
+        let width = rows.iter().flatten().map(|s| s.len()).max().unwrap_or(1);
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|s| format!("{:>width$}", s, width = width))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 }
 
-pub trait ModRow {
-    fn add_random_zero(self) -> bool;
-    fn combine_row(&mut self) -> bool;
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Notation {
+    Exponents,
+    Values,
+    Compact,
 }
 
-impl ModRow for Box<[&mut Cell]> {
-    fn add_random_zero(mut self) -> bool {
-        let mut rng = thread_rng();
-        self.shuffle(&mut rng);
+fn parse_grid<F>(s: &str, parse_cell: F) -> Result<Vec<Vec<Option<u32>>>, String>
+where
+    F: Fn(&str) -> Result<Option<u32>, String>,
+{
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split_whitespace().map(&parse_cell).collect())
+        .collect()
+}
 
-        for n in self.iter_mut() {
-            if !n.is_set() {
-                n.set_score(0);
-                return true;
+fn parse_exponent_cell(token: &str) -> Result<Option<u32>, String> {
+    match token {
+        "*" | "." => Ok(None),
+        _ => match token.parse::<u32>() {
+            Ok(score) if score < 32 => Ok(Some(score)),
+            _ => Err(format!("invalid exponent '{}'", token)),
+        },
+    }
+}
+
+fn parse_value_cell(token: &str) -> Result<Option<u32>, String> {
+    match token {
+        "." | "0" => Ok(None),
+        _ => match token.parse::<u64>() {
+            Ok(value) if value.is_power_of_two() && value < 1 << 32 => {
+                Ok(Some(value.trailing_zeros()))
             }
+            _ => Err(format!("invalid tile '{}'", token)),
+        },
+    }
+}
+
+fn parse_compact_cell(c: char) -> Result<Option<u32>, String> {
+    match c {
+        '.' => Ok(None),
+        _ => match c.to_digit(36) {
+            Some(score) if score < 32 => Ok(Some(score)),
+            _ => Err(format!("invalid cell '{}'", c)),
+        },
+    }
+}
+
+impl FromStr for Board {
+    type Err = String;
+
+    /// Single line input is read as `Compact`, grids containing `*` as `Exponents` and grids
+    /// containing `.` or tiles too large to be exponents as `Values`. Use `Board::parse` to
+    /// pick the notation of a full grid of small tiles explicitly.
+    fn from_str(s: &str) -> Result<Board, String> {
+        let s = s.trim();
+        let mut tokens = s.split_whitespace();
+        let notation = if !s.contains('\n') {
+            Notation::Compact
+        } else if tokens.clone().any(|token| token == "*") {
+            Notation::Exponents
+        } else if tokens
+            .any(|token| token == "." || token.parse::<u64>().map_or(false, |v| v >= 32))
+        {
+            Notation::Values
+        } else {
+            Notation::Exponents
+        };
+        Board::parse(s, notation)
+    }
+}
+
+impl fmt::Display for Board {
+    /// Exponent grid, or the tile value grid with `{:#}`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{}", self.to_notation(Notation::Values))
+        } else {
+            write!(f, "{}", self.to_notation(Notation::Exponents))
         }
-        false
     }
+}
 
-    fn combine_row(&mut self) -> bool {
-        let mut ok = false;
-        for index in 0..self.len() {
-            for i in index + 1..self.len() {
-                if self[index].is_set() {
-                    if self[index].get_score() == self[i].get_score() {
-                        self[index].incr_score();
-                        self[i].set_none();
-                        ok = true;
-                        break;
-                    } else if self[i].is_set() {
-                        break;
-                    }
-                } else if self[i].is_set() {
-                    self[index].set_score(self[i].get_score().unwrap());
-                    self[i].set_none();
+/// Slides every run of cells between blocks of `line` on its own.
+fn slide_segments(rules: &dyn Ruleset, line: &mut [&mut Cell], score: &mut u64) -> bool {
+    let mut moved = false;
+    for segment in line.split_mut(|cell| cell.is_blocked()) {
+        if rules.slide_line(segment, score) {
+            moved = true;
+        }
+    }
+    moved
+}
+
+/// Packs a line towards its first cell, merging each pair of equal tiles once.
+pub fn combine_line(line: &mut [&mut Cell]) -> bool {
+    combine_line_with(line, merge_equal)
+}
+
+/// Equal tiles make the next one.
+pub fn merge_equal(a: u32, b: u32) -> Option<u32> {
+    if a == b {
+        Some(a + 1)
+    } else {
+        None
+    }
+}
+
+/// Like `combine_line` with `merge` deciding which pairs of tiles merge and into what.
+pub fn combine_line_with<F>(line: &mut [&mut Cell], mut merge: F) -> bool
+where
+    F: FnMut(u32, u32) -> Option<u32>,
+{
+    let mut ok = false;
+    for index in 0..line.len() {
+        for i in index + 1..line.len() {
+            if let Some(a) = line[index].get_score() {
+                if let Some(merged) = line[i].get_score().and_then(|b| merge(a, b)) {
+                    line[index].set_score(merged);
+                    line[i].set_none();
                     ok = true;
+                    break;
+                } else if line[i].is_set() {
+                    break;
                 }
+            } else if line[i].is_set() {
+                line[index].set_score(line[i].get_score().unwrap());
+                line[i].set_none();
+                ok = true;
             }
         }
-        ok
     }
+    ok
 }
 
-#[derive(Debug, Clone)]
+/// A tile, an empty cell or a block that tiles can't enter.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Cell {
     score: Option<u32>,
+    blocked: bool,
 }
 
 impl Cell {
     fn new() -> Cell {
-        Cell { score: None }
+        Cell {
+            score: None,
+            blocked: false,
+        }
     }
 
     pub fn is_set(&self) -> bool {
         self.score.is_some()
     }
 
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+
+    /// Neither holding a tile nor blocked.
+    pub fn is_free(&self) -> bool {
+        !self.is_set() && !self.blocked
+    }
+
+    /// Turns the cell into a block, removing its tile.
+    pub fn set_blocked(&mut self) {
+        self.score = None;
+        self.blocked = true;
+    }
+
     pub fn get_score(&self) -> Option<u32> {
         self.score
     }
@@ -301,18 +927,323 @@ impl Cell {
     }
 
     pub fn as_symbol(&self) -> String {
-        if self.is_set() {
+        if self.blocked {
+            return String::from("#");
+        } else if self.is_set() {
             return self.score.unwrap().to_string();
         } else {
             return String::from("*");
         }
     }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::rules::Classic;
+    use crate::symmetry::Transform;
+
+    const CASES: usize = 500;
+    const SEED: u64 = 2048;
+
+    /// A board with roughly `empty` of its cells free and small exponents, so merges are common.
+    pub fn random_board<R: Rng>(rng: &mut R, width: usize, height: usize, empty: f64) -> Board {
+        let rows: Vec<Vec<Option<u32>>> = (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| {
+                        if rng.gen_bool(empty) {
+                            None
+                        } else {
+                            Some(rng.gen_range(0, 4))
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Board::from_exponents(&rows).unwrap()
+    }
+
+    fn boards() -> Vec<Board> {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        (0..CASES)
+            .map(|i| {
+                let (width, height) = if i % 5 == 0 { (3, 5) } else { (4, 4) };
+                let mut board = random_board(&mut rng, width, height, 0.3);
+                if i % 7 == 0 {
+                    let (x, y) = (rng.gen_range(0, width), rng.gen_range(0, height));
+                    board.get_mut_cell(x, y).set_blocked();
+                }
+                board
+            })
+            .collect()
+    }
+
+    fn tile_sum(board: &Board) -> u64 {
+        board
+            .board_data()
+            .iter()
+            .filter_map(|cell| cell.get_score())
+            .map(|score| 2u64.pow(score))
+            .sum()
+    }
+
+    /// The tiles of line `i` of a move in `dir`, starting from the edge they move towards.
+    fn line(board: &Board, dir: Direction, i: usize) -> Vec<u32> {
+        let (width, height) = (board.width(), board.height());
+        let cells: Vec<(usize, usize)> = match dir {
+            Direction::LEFT => (0..width).map(|x| (x, i)).collect(),
+            Direction::RIGHT => (0..width).rev().map(|x| (x, i)).collect(),
+            Direction::UP => (0..height).map(|y| (i, y)).collect(),
+            Direction::DOWN => (0..height).rev().map(|y| (i, y)).collect(),
+        };
+        cells
+            .into_iter()
+            .filter_map(|(x, y)| board.get_cell(x, y).get_score())
+            .collect()
+    }
+
+    fn show(board: &Board) -> String {
+        board.to_notation(Notation::Compact)
+    }
+
+    fn stepped(board: &Board, dir: Direction) -> (Board, bool) {
+        let mut board = board.clone();
+        let moved = board.step_rows(dir);
+        (board, moved)
+    }
+
+    #[test]
+    fn slides_keep_the_tile_sum() {
+        for board in boards() {
+            for &dir in Direction::all().iter() {
+                assert_eq!(
+                    tile_sum(&stepped(&board, dir).0),
+                    tile_sum(&board),
+                    "{} {}",
+                    dir,
+                    show(&board)
+                );
+            }
+        }
+    }
 
-    pub fn as_string(&self) -> String {
-        if self.is_set() {
-            return 2_i32.pow(self.score.unwrap()).to_string();
-        } else {
-            return String::from("");
+    #[test]
+    fn spawns_add_a_single_one() {
+        for board in boards() {
+            for &dir in Direction::all().iter() {
+                let (slid, moved) = stepped(&board, dir);
+                if !moved {
+                    continue;
+                }
+                let mut spawned = slid.clone();
+                match spawned.step_add(dir) {
+                    Some(_) => assert_eq!(
+                        tile_sum(&spawned),
+                        tile_sum(&slid) + 1,
+                        "{} {}",
+                        dir,
+                        show(&board)
+                    ),
+                    None => assert_eq!(spawned, slid, "{} {}", dir, show(&board)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn moves_are_no_ops_exactly_when_step_rows_fails() {
+        for board in boards() {
+            for &dir in Direction::all().iter() {
+                let (slid, moved) = stepped(&board, dir);
+                assert_eq!(moved, slid != board, "{} {}", dir, show(&board));
+            }
+        }
+    }
+
+    #[test]
+    fn left_and_right_are_mirror_images() {
+        let flip = Transform::FlipHorizontal;
+        for board in boards() {
+            let (left, left_moved) = stepped(&board, Direction::LEFT);
+            let (right, right_moved) = stepped(&board.transformed(flip), Direction::RIGHT);
+            assert_eq!(left_moved, right_moved, "{}", show(&board));
+            assert_eq!(left, right.transformed(flip), "{}", show(&board));
         }
     }
+
+    #[test]
+    fn vertical_moves_match_horizontal_moves_on_the_transpose() {
+        let transpose = Transform::Transpose;
+        for board in boards() {
+            for &(vertical, horizontal) in [
+                (Direction::UP, Direction::LEFT),
+                (Direction::DOWN, Direction::RIGHT),
+            ]
+            .iter()
+            {
+                let (moved, _) = stepped(&board, vertical);
+                let (transposed, _) = stepped(&board.transformed(transpose), horizontal);
+                assert_eq!(
+                    moved,
+                    transposed.transformed(transpose),
+                    "{} {}",
+                    vertical,
+                    show(&board)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_merge_at_most_once_per_move() {
+        for board in boards() {
+            for &dir in Direction::all().iter() {
+                let (slid, _) = stepped(&board, dir);
+                let lines = match dir {
+                    Direction::LEFT | Direction::RIGHT => board.height(),
+                    Direction::UP | Direction::DOWN => board.width(),
+                };
+                let case = format!("{} {}", dir, show(&board));
+                for i in 0..lines {
+                    let before = line(&board, dir, i);
+                    let after = line(&slid, dir, i);
+                    // Every merge takes exactly two tiles, so at least half of them survive.
+                    assert!(after.len() * 2 >= before.len(), "{}", case);
+                    // Tiles stay sorted by the position of the tiles they came from, so reading
+                    // both lines in order must pair every new tile with one or two old ones.
+                    let mut old = before.iter();
+                    for &tile in after.iter() {
+                        let first = *old.next().expect(&case);
+                        if first != tile {
+                            assert_eq!(first + 1, tile, "{}", case);
+                            assert_eq!(old.next(), Some(&first), "{}", case);
+                        }
+                    }
+                    assert!(old.next().is_none(), "{}", case);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merged_tiles_do_not_merge_again() {
+        let cases = [
+            ("0001", "101."),
+            ("1100", "21.."),
+            ("0000", "11.."),
+            ("0.01", "11.."),
+            ("1001", "111."),
+            ("2110", "220."),
+        ];
+        for &(row, expected) in cases.iter() {
+            let mut board = Board::parse(row, Notation::Compact).unwrap();
+            board.step_rows(Direction::LEFT);
+            assert_eq!(board.to_notation(Notation::Compact), expected, "{}", row);
+        }
+    }
+
+    #[test]
+    fn notations_round_trip() {
+        let board = Board::parse("0.3/#a.", Notation::Compact).unwrap();
+        assert_eq!((board.width(), board.height()), (3, 2));
+        assert_eq!(board.get_cell(2, 0).get_score(), Some(3));
+        assert_eq!(board.get_cell(1, 1).get_score(), Some(10));
+        assert!(board.get_cell(0, 1).is_blocked());
+
+        let written = [
+            (Notation::Compact, "0.3/#a."),
+            (Notation::Exponents, " 0  *  3\n # 10  *"),
+            (Notation::Values, "   1    .    8\n   # 1024    ."),
+        ];
+        for &(notation, s) in written.iter() {
+            assert_eq!(board.to_notation(notation), s);
+            assert_eq!(Board::parse(s, notation).unwrap(), board, "{}", s);
+            assert_eq!(s.parse::<Board>().unwrap(), board, "{}", s);
+        }
+        assert_eq!(board.to_string().parse::<Board>().unwrap(), board);
+    }
+
+    #[test]
+    fn invalid_notations_are_rejected() {
+        let invalid = [
+            (Notation::Compact, ""),
+            (Notation::Compact, "0./0"),
+            (Notation::Compact, "0w"),
+            (Notation::Compact, "0!"),
+            (Notation::Exponents, "0 *\n0"),
+            (Notation::Exponents, "0 32"),
+            (Notation::Exponents, "0 x"),
+            (Notation::Values, "2 3"),
+            (Notation::Values, "2 .\n2"),
+            (Notation::Values, "2 4294967296"),
+        ];
+        for &(notation, s) in invalid.iter() {
+            assert!(Board::parse(s, notation).is_err(), "{:?} {:?}", notation, s);
+        }
+    }
+
+    #[test]
+    fn blocks_cut_lines_into_segments() {
+        let cases = [
+            ("00#0", Direction::LEFT, "1.#0"),
+            ("0.#.0", Direction::LEFT, "0.#0."),
+            ("1.#1", Direction::RIGHT, ".1#1"),
+            ("#11.", Direction::LEFT, "#2.."),
+            ("0#0/0#0", Direction::UP, "1#1/.#."),
+        ];
+        for &(board, dir, expected) in cases.iter() {
+            let (slid, _) = stepped(&Board::parse(board, Notation::Compact).unwrap(), dir);
+            assert_eq!(slid.to_notation(Notation::Compact), expected, "{}", board);
+        }
+        let stuck = Board::parse("0#/#1", Notation::Compact).unwrap();
+        assert!(!stuck.has_moves());
+
+        // Spawns never land on blocks, however often they are drawn.
+        let blocks = [(0, 0), (1, 2), (3, 3)];
+        for _ in 0..20 {
+            let mut game = Game::with_blocks(&EdgeSpawn, 11, true, &blocks).unwrap();
+            while let Some(&dir) = game.get_board().legal_moves().first() {
+                game.step(dir);
+            }
+            assert_eq!(game.get_board().blocked_cells(), blocks.to_vec());
+            game.reset();
+            assert_eq!(game.get_board().blocked_cells(), blocks.to_vec());
+        }
+        assert!(Game::with_blocks(&EdgeSpawn, 11, true, &[(4, 0)]).is_err());
+    }
+
+    #[test]
+    fn games_without_a_first_move_start_lost() {
+        // Only the cells in the odd columns of the first and third rows are free, none of them
+        // has a neighbour a tile could move to.
+        let blocks: Vec<(usize, usize)> = (0..4)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&(x, y)| x % 2 == 0 || y % 2 == 1)
+            .collect();
+        for &rules in [&EdgeSpawn as &dyn Ruleset, &Classic].iter() {
+            let mut game = Game::with_blocks(rules, 11, false, &blocks).unwrap();
+            assert!(!game.get_board().has_moves());
+            assert_eq!(game.status(), GameStatus::Lost);
+            assert!(game.is_over());
+            game.reset();
+            assert_eq!(game.status(), GameStatus::Lost);
+        }
+    }
+
+    #[test]
+    fn merge_score_adds_up_the_merged_tiles() {
+        let mut game = Game::new();
+        game.set_board(Board::parse("0011/2.2./..../....", Notation::Compact).unwrap());
+        assert!(game.step(Direction::LEFT));
+        // 2 + 4 on the first row and 8 on the second.
+        assert_eq!(game.merge_score(), 14);
+
+        game.set_board(Board::parse("01/..", Notation::Compact).unwrap());
+        assert_eq!(game.merge_score(), 0);
+    }
 }
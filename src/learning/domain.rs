@@ -3,13 +3,48 @@ use rsrl::{
     geometry::{continuous::Interval, discrete::Ordinal, product::LinearSpace, Surjection, Vector},
 };
 
-use crate::game::{Board, Cell, Direction, Game};
+use std::str::FromStr;
+
+use crate::game::{Board, Cell, Direction, Game, GameStatus};
 
 const REWARD_STEP: f64 = -1.0;
 const REWARD_GOAL: f64 = 0.0;
 
+/// What a move that doesn't end the game is rewarded with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reward {
+    /// One plus the cells the move freed.
+    FreeCells,
+    /// The gain in `Game::get_score`.
+    Score,
+    /// The values of the tiles the move merged, see `Game::merge_score`.
+    MergeScore,
+}
+
+impl Default for Reward {
+    fn default() -> Reward {
+        Reward::FreeCells
+    }
+}
+
+impl FromStr for Reward {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Reward, String> {
+        match s {
+            "cells" => Ok(Reward::FreeCells),
+            "score" => Ok(Reward::Score),
+            "merges" => Ok(Reward::MergeScore),
+            _ => Err(format!("unknown reward '{}'", s)),
+        }
+    }
+}
+
 pub struct GameDomain {
     game: Game,
+    reward: Reward,
+    /// Score gained by the last step, in the metric `reward` picks.
+    gained: f64,
 }
 
 impl From<usize> for Direction {
@@ -25,7 +60,7 @@ impl From<usize> for Direction {
     }
 }
 
-trait GameState {
+pub trait GameState {
     fn get_state(&self) -> Vec<f64>;
 }
 
@@ -45,14 +80,34 @@ impl GameState for Game {
 
 impl Default for GameDomain {
     fn default() -> GameDomain {
-        GameDomain { game: Game::new() }
+        GameDomain::new(Reward::default())
     }
 }
 
 impl GameDomain {
+    pub fn new(reward: Reward) -> GameDomain {
+        GameDomain {
+            game: Game::new(),
+            reward,
+            gained: 0.0,
+        }
+    }
+
+    fn score(&self) -> f64 {
+        match self.reward {
+            Reward::FreeCells => 0.0,
+            Reward::Score => self.game.get_score() as f64,
+            Reward::MergeScore => self.game.merge_score() as f64,
+        }
+    }
+
     pub fn get_score(&self) -> i32 {
         self.game.get_score()
     }
+
+    pub fn status(&self) -> GameStatus {
+        self.game.status()
+    }
 }
 
 impl Domain for GameDomain {
@@ -72,7 +127,9 @@ impl Domain for GameDomain {
     fn step(&mut self, action: usize) -> Transition<Vector<f64>, usize> {
         let from = self.emit();
 
+        let before = self.score();
         self.game.step(Direction::from(action));
+        self.gained = self.score() - before;
         let to = self.emit();
         let reward = self.reward(&from, &to);
 
@@ -89,6 +146,9 @@ impl Domain for GameDomain {
     }
 
     fn reward(&self, from: &Observation<Vector<f64>>, to: &Observation<Vector<f64>>) -> f64 {
+        if self.reward != Reward::FreeCells {
+            return self.gained;
+        }
         let from = match from {
             Observation::Full(s) | Observation::Terminal(s) => {
                 let mut c = 0;
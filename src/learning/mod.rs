@@ -1,24 +1,57 @@
 mod domain;
 
-pub use domain::GameDomain;
+pub use domain::{GameDomain, Reward};
+
+use domain::GameState;
 
 use rsrl::{
-    control::td::QLearning,
+    control::{td::QLearning, Controller},
     core::{make_shared, run, Evaluation, Parameter, SerialExperiment},
-    domains::{CartPole, Domain},
+    domains::{CartPole, Domain, Observation},
     fa::{basis::fixed::Chebyshev, LFA},
-    geometry::Space,
+    geometry::{Space, Vector},
     logging,
     policies::fixed::{EpsilonGreedy, Greedy, Random},
 };
 
+use crate::agent::Agent;
+use crate::game::{Direction, Game, GameStatus};
+
+/// Plays the greedy action of a trained controller, falling back to the first legal move when
+/// the policy picks one that doesn't move anything.
+pub struct LearnedAgent<C> {
+    controller: C,
+}
+
+impl<C: Controller<Vector<f64>, usize>> Agent for LearnedAgent<C> {
+    fn name(&self) -> &str {
+        "learned"
+    }
+
+    fn choose_move(&mut self, game: &Game) -> Option<Direction> {
+        let legal = game.get_board().legal_moves();
+        let state = Vector::from_vec(game.get_state());
+        let dir = Direction::from(self.controller.sample_target(&state));
+        if legal.contains(&dir) {
+            Some(dir)
+        } else {
+            legal.first().cloned()
+        }
+    }
+}
+
 pub struct Learning {}
 
 impl Learning {
-    pub fn learn() {
+    pub fn learn(reward: Reward) {
+        Learning::train(None, reward);
+    }
+
+    /// Trains a Q-learning agent for `batches` batches of 100 episodes, or forever when `None`.
+    pub fn train(batches: Option<usize>, reward: Reward) -> Box<dyn Agent> {
         let logger = logging::root(logging::stdout());
 
-        let domain = GameDomain::default();
+        let domain = GameDomain::new(reward);
         let mut agent = {
             let n_actions = domain.action_space().card().into();
 
@@ -38,22 +71,60 @@ impl Learning {
         };
 
         let mut c = 0;
-        loop {
-            c+=1;
+        while batches.map_or(true, |batches| c < batches) {
+            c += 1;
             // Training phase:
             let _training_result = {
                 // Start a serial learning experiment up to 1000 steps per episode.
-                let e = SerialExperiment::new(&mut agent, Box::new(GameDomain::default), 1000);
+                let e = SerialExperiment::new(
+                    &mut agent,
+                    Box::new(move || GameDomain::new(reward)),
+                    1000,
+                );
 
                 // Realise 1000 episodes of the experiment generator.
                 run(e, 100, Some(logger.clone()))
             };
 
             // Testing phase:
-            let testing_result = Evaluation::new(&mut agent, Box::new(GameDomain::default)).next().unwrap();
+            let testing_result =
+                Evaluation::new(&mut agent, Box::new(move || GameDomain::new(reward)))
+                    .next()
+                    .unwrap();
+
+            let (won, lost) = Learning::play(&mut agent, reward, 10);
 
             info!(logger, "batch {}", c);
             info!(logger, "solution"; testing_result);
+            info!(logger, "games"; "won" => won, "lost" => lost);
+        }
+
+        Box::new(LearnedAgent { controller: agent })
+    }
+
+    /// Plays `games` greedy games of up to 1000 steps, returns how many were won and lost.
+    fn play<C: Controller<Vector<f64>, usize>>(
+        controller: &mut C,
+        reward: Reward,
+        games: usize,
+    ) -> (usize, usize) {
+        let mut won = 0;
+        let mut lost = 0;
+        for _ in 0..games {
+            let mut domain = GameDomain::new(reward);
+            for _ in 0..1000 {
+                let state = match domain.emit() {
+                    Observation::Full(s) => s,
+                    _ => break,
+                };
+                domain.step(controller.sample_target(&state));
+            }
+            match domain.status() {
+                GameStatus::Won => won += 1,
+                GameStatus::Lost => lost += 1,
+                GameStatus::Playing => {}
+            }
         }
+        (won, lost)
     }
 }
@@ -0,0 +1,35 @@
+extern crate dirs;
+extern crate rand;
+#[cfg(feature = "learning")]
+extern crate rsrl;
+#[cfg(feature = "gui")]
+extern crate sdl2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate tiny_http;
+
+#[cfg(feature = "learning")]
+#[macro_use]
+extern crate slog;
+
+pub mod agent;
+pub mod ai;
+pub mod engine;
+pub mod game;
+#[cfg(feature = "learning")]
+pub mod learning;
+pub mod mcts;
+pub mod monte_carlo;
+pub mod record;
+#[cfg(test)]
+mod reference;
+pub mod rules;
+pub mod server;
+pub mod spawner;
+pub mod stats;
+pub mod symmetry;
+#[cfg(feature = "gui")]
+pub mod ui;
@@ -1,91 +1,484 @@
-extern crate rand;
-extern crate rsrl;
-extern crate sdl2;
+#[cfg(feature = "gui")]
+use auto2048::ui::App;
 
-#[macro_use]
-extern crate slog;
+use auto2048::engine::{self, Engine};
+#[cfg(feature = "learning")]
+use auto2048::learning::{Learning, Reward};
 
-use rand::prelude::*;
-use rand::{thread_rng, Rng};
+use auto2048::agent::{self, Agent, SearchAgent};
+use auto2048::ai::AIScore;
+use auto2048::ai::SearchStats;
+use auto2048::game::{Board, Direction, Game, GameStatus, Notation};
+use auto2048::mcts::{Budget, MctsConfig};
+use auto2048::monte_carlo::MonteCarloConfig;
+use auto2048::record::Record;
+use auto2048::rules::{self, Ruleset};
+use auto2048::server;
+use auto2048::spawner::{self, Spawner};
+use auto2048::stats::{self, GameResult};
 
-mod ai;
-mod game;
-mod learning;
-mod ui;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use ui::App;
+fn get_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
 
-use crate::learning::Learning;
+fn get_option<T: FromStr>(args: &[String], name: &str) -> Option<T> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .and_then(|value| value.parse().ok())
+}
 
-use crate::ai::AIScore;
-use crate::ai::AI;
-use crate::game::{Board, Direction, Game};
+/// The ruleset picked with `--rules`, the edge spawn rules by default.
+fn make_rules(args: &[String]) -> Result<&'static dyn Ruleset, String> {
+    let name: String = get_option(args, "--rules").unwrap_or_else(|| "edge".to_string());
+    rules::by_name(&name)
+}
+
+/// The blocked cells of the `--blocks` layout, see `engine::parse_blocks`.
+fn make_blocks(args: &[String]) -> Result<Vec<(usize, usize)>, String> {
+    match get_option::<String>(args, "--blocks") {
+        Some(layout) => engine::parse_blocks(&layout),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The statistics file, the path after `--stats` or the default one.
+fn stats_path(args: &[String]) -> Result<PathBuf, String> {
+    match get_option::<String>(args, "--stats") {
+        Some(path) if !path.starts_with("--") => Ok(PathBuf::from(path)),
+        _ => stats::default_path(),
+    }
+}
+
+/// Adds finished games to the statistics file, only when `--stats` is given.
+fn record_stats(args: &[String], results: &[GameResult]) {
+    if !get_flag(args, "--stats") {
+        return;
+    }
+    if let Err(e) = stats_path(args).and_then(|path| stats::record(&path, results)) {
+        eprintln!("failed to record the games: {}", e);
+    }
+}
+
+/// Builds the spawner picked with `--spawner`, the evil one searches `--spawn-depth` plies.
+fn make_spawner(args: &[String]) -> Result<Box<dyn Spawner>, String> {
+    let name: String = get_option(args, "--spawner").unwrap_or_else(|| "random".to_string());
+    spawner::by_name(&name, get_option(args, "--spawn-depth").unwrap_or(4))
+}
+
+/// Builds the agent picked with `--agent`, or `default` when none is given. Minimax searches
+/// to `--depth`, MCTS is configured with `--iterations`, `--time-ms`, `--rollout` and
+/// `--rollout-depth`, the Monte Carlo agent with `--rollouts`, `--rollout-depth`, `--scoring`
+/// and `--threads` and the learned policy trains for `--batches` on the `--reward`. Other agents
+/// take no options.
+fn make_agent(args: &[String], board: &Board, default: &str) -> Result<Box<dyn Agent>, String> {
+    let agent: String = get_option(args, "--agent").unwrap_or_else(|| default.to_string());
+    let depth = get_option(args, "--depth").unwrap_or(agent::DEFAULT_DEPTH);
+    match agent.as_str() {
+        "minimax" => Ok(Box::new(SearchAgent::minimax(board, depth))),
+        "noise" => Ok(Box::new(SearchAgent::noise(board, depth))),
+        "mcts" => {
+            let mut config = MctsConfig::default();
+            match get_option(args, "--iterations") {
+                Some(0) => return Err("--iterations must be at least 1".to_string()),
+                Some(iterations) => config.budget = Budget::Iterations(iterations),
+                None => {}
+            }
+            if let Some(ms) = get_option(args, "--time-ms") {
+                config.budget = Budget::Time(Duration::from_millis(ms));
+            }
+            if let Some(rollout) = get_option::<String>(args, "--rollout") {
+                config.rollout = rollout.parse()?;
+            }
+            if let Some(depth) = get_option(args, "--rollout-depth") {
+                config.rollout_depth = depth;
+            }
+            Ok(Box::new(SearchAgent::mcts(board, config)))
+        }
+        "mc" => {
+            let mut config = MonteCarloConfig::default();
+            match get_option(args, "--rollouts") {
+                Some(0) => return Err("--rollouts must be at least 1".to_string()),
+                Some(rollouts) => config.rollouts = rollouts,
+                None => {}
+            }
+            if let Some(depth) = get_option(args, "--rollout-depth") {
+                config.depth = Some(depth);
+            }
+            if let Some(scoring) = get_option(args, "--scoring") {
+                config.scoring = scoring;
+            }
+            if let Some(threads) = get_option(args, "--threads") {
+                config.threads = threads;
+            }
+            Ok(Box::new(SearchAgent::monte_carlo(board, config)))
+        }
+        #[cfg(feature = "learning")]
+        "learned" => Ok(Learning::train(
+            Some(get_option(args, "--batches").unwrap_or(agent::DEFAULT_BATCHES)),
+            make_reward(args)?,
+        )),
+        agent => agent::by_name(agent, board),
+    }
+}
+
+/// The learning reward picked with `--reward`: `cells`, `score` or `merges`.
+#[cfg(feature = "learning")]
+fn make_reward(args: &[String]) -> Result<Reward, String> {
+    let name: String = get_option(args, "--reward").unwrap_or_else(|| "cells".to_string());
+    name.parse()
+}
 
-use std::env;
 fn main() {
     let mut args = env::args().into_iter();
     let target = args.nth(1).unwrap_or("gui".to_string());
+    let args: Vec<String> = args.collect();
     if target == "gui" {
-        let app = App::new();
-        app.run_app().unwrap();
+        #[cfg(feature = "gui")]
+        App::new().run_app().unwrap();
+        #[cfg(not(feature = "gui"))]
+        eprintln!("built without the gui feature");
     } else if target == "bench" {
+        let rules = match make_rules(&args) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let target_tile = match get_option::<u64>(&args, "--target") {
+            Some(value) => match rules.tile_code(value) {
+                Some(tile) => tile,
+                None => {
+                    eprintln!(
+                        "target {} is not a tile of the {} rules",
+                        value,
+                        rules.name()
+                    );
+                    return;
+                }
+            },
+            None => rules.default_target(),
+        };
+        let dump_tree: Option<String> = get_option(&args, "--dump-tree");
+        let dump_depth = get_option(&args, "--dump-depth").unwrap_or(3);
+        let blocks = make_blocks(&args);
+        let endless = get_flag(&args, "--endless");
+        let mut game = match blocks.and_then(|b| Game::with_blocks(rules, target_tile, endless, &b))
+        {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let record_path: Option<String> = get_option(&args, "--record");
+        let mut record = Record::new(&game);
+
+        let start = Instant::now();
+        let mut timestamps = Vec::new();
+        let mut stats = SearchStats::default();
+        let reuse = !get_flag(&args, "--no-reuse");
+        let mut spawner = match make_spawner(&args) {
+            Ok(spawner) => spawner,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let mut agent = match make_agent(&args, game.get_board(), "minimax") {
+            Ok(agent) => agent,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        while !game.is_over() {
+            let dir = agent.choose_move(&game);
+            if let Some(search) = agent.last_search() {
+                stats.merge(search.stats());
+            }
+            // Overwritten every move, so the file ends up holding the decision that ended the game.
+            if let Some(path) = dump_tree.as_ref() {
+                if let Err(e) = agent.export_dot(Path::new(path), dump_depth) {
+                    eprintln!("failed to dump the search tree: {}", e);
+                }
+            }
+            let dir = match dir {
+                Some(dir) => dir,
+                None => break,
+            };
+            game.step_with(dir, spawner.as_mut());
+            record.push(dir, &game);
+            // Only searches have a tree to throw away, other agents would just be built again.
+            if !reuse && agent.last_search().is_some() {
+                agent = make_agent(&args, game.get_board(), "minimax").unwrap();
+            }
+            while timestamps.len() < game.milestones().len() {
+                timestamps.push(start.elapsed());
+            }
+        }
+
+        record_stats(
+            &args,
+            &[GameResult::new(
+                &game,
+                "bench",
+                agent.name(),
+                start.elapsed(),
+            )],
+        );
+        for (&(tile, moves), time) in game.milestones().iter().zip(timestamps.iter()) {
+            println!("{}\tmove {}\t{:?}", rules.tile_value(tile), moves, time);
+        }
+        if let Some(moves) = game.reached_target() {
+            println!(
+                "reached {} after {} moves",
+                rules.tile_value(target_tile),
+                moves
+            );
+        }
+        if stats.searches > 0 {
+            println!("search\t{}", stats);
+            println!(
+                "per move\t{} nodes\t{} created\t{} reused",
+                stats.nodes() / stats.searches,
+                stats.nodes_created / stats.searches,
+                stats.nodes_reused / stats.searches
+            );
+        }
+        if let Some(path) = record_path {
+            if let Err(e) = fs::write(&path, record.to_string()) {
+                eprintln!("failed to write the record: {}", e);
+            }
+        }
+        match game.status() {
+            GameStatus::Won => println!(
+                "game won with a score of {} ({} merged) after {} moves against {} spawns",
+                game.get_score(),
+                game.merge_score(),
+                game.moves(),
+                spawner.name()
+            ),
+            _ => println!(
+                "game lost with a score of {} ({} merged) after {} moves against {} spawns",
+                game.get_score(),
+                game.merge_score(),
+                game.moves(),
+                spawner.name()
+            ),
+        }
+    } else if target == "engine" {
+        Engine::run().unwrap();
+    } else if target == "serve" {
+        let port = get_option(&args, "--port").unwrap_or(8048);
+        server::serve(port).unwrap();
+    } else if target == "analyze" {
+        let rules = match make_rules(&args) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let board: Board = match args.first().map(|s| s.parse::<Board>()) {
+            Some(Ok(board)) => board.with_rules(rules),
+            Some(Err(e)) => {
+                eprintln!("invalid board: {}", e);
+                return;
+            }
+            None => {
+                eprintln!(
+                    "usage: analyze \"<board>\" [--rules NAME] [--agent NAME] [--depth N] [--dump-tree PATH] [--dump-depth N]"
+                );
+                return;
+            }
+        };
+        println!("{}\n", board);
+        println!("{}", board.to_notation(Notation::Compact));
+        let (canonical, transform) = board.canonical();
+        println!(
+            "canonical\t{}\t{:?}",
+            canonical.to_notation(Notation::Compact),
+            transform
+        );
+        println!("heuristic\t{}", board.get_ai_score());
+        let mut agent = match make_agent(&args, &board, "minimax") {
+            Ok(agent) => agent,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
         let mut game = Game::new();
-        let mut run = true;
-        let mut steps = 0;
-        let mut won = false;
-
-        while run {
-            //let mut ai = AI::new(&game.get_board(), 6);
-            let mut ai = AI::new(&game.get_board(), 6);
-            ai.build_tree();
-            let minimax = ai.minimax(None);
-            if game.step(minimax.get_direction()) == false || game.has_won() {
-                won = game.has_won();
-                run = false;
-            }
-            steps += 1;
-        }
-        if won {
-            println!("game won with a score of {}", game.get_score());
-        } else {
-            println!("game lost with a score of {}", game.get_score());
+        game.set_board(board);
+        let dir = agent.choose_move(&game);
+        if let Some(path) = get_option::<String>(&args, "--dump-tree") {
+            let dump_depth = get_option(&args, "--dump-depth").unwrap_or(3);
+            if let Err(e) = agent.export_dot(Path::new(&path), dump_depth) {
+                eprintln!("failed to dump the search tree: {}", e);
+            }
+        }
+        let legal = game.get_board().legal_moves();
+        for &dir in Direction::all().iter() {
+            let score = agent
+                .last_search()
+                .and_then(|search| search.root_scores().iter().find(|(d, _)| *d == dir));
+            match score {
+                Some((_, score)) => println!("{}\t{}", dir, score),
+                None if legal.contains(&dir) => println!("{}\tlegal", dir),
+                None => println!("{}\tillegal", dir),
+            }
+        }
+        match (dir, agent.last_search()) {
+            (Some(dir), Some(search)) => {
+                println!("best\t{}\t{}", dir, search.get_score());
+                let pv: Vec<String> = search
+                    .principal_variation()
+                    .iter()
+                    .map(|step| step.to_string())
+                    .collect();
+                println!("pv\t{}", pv.join(", "));
+                println!("search\t{}", search.stats());
+            }
+            (Some(dir), None) => println!("best\t{}", dir),
+            (None, _) => println!("no legal moves"),
+        }
+    } else if target == "replay" {
+        let path = match args.first() {
+            Some(path) => path,
+            None => {
+                eprintln!("usage: replay <record>");
+                return;
+            }
+        };
+        let record = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| s.parse::<Record>());
+        match record.and_then(|record| record.replay()) {
+            Ok(game) => {
+                println!("{}\n", game.get_board());
+                println!(
+                    "{} with a score of {} ({} merged) after {} moves",
+                    engine::status_name(game.status()),
+                    game.get_score(),
+                    game.merge_score(),
+                    game.moves()
+                );
+            }
+            Err(e) => eprintln!("invalid record: {}", e),
         }
     } else if target == "learn" {
-        Learning::learn();
+        #[cfg(feature = "learning")]
+        match make_reward(&args) {
+            Ok(reward) => Learning::learn(reward),
+            Err(e) => eprintln!("{}", e),
+        }
+        #[cfg(not(feature = "learning"))]
+        eprintln!("built without the learning feature");
     } else if target == "rand" {
-        let mut game = Game::new();
-        let mut run = true;
-        let mut steps = 0;
-        let mut won = false;
+        let n = get_option(&args, "--games").unwrap_or(100);
 
         let mut scores = Vec::new();
+        let mut results = Vec::new();
+        let mut wins = 0;
+        let mut losses = 0;
 
-        let n = 100000000;
+        let rules = match make_rules(&args) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let blocks = match make_blocks(&args) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let new_game = || Game::with_blocks(rules, rules.default_target(), false, &blocks);
+        let first = match new_game() {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let agent = make_agent(&args, first.get_board(), "noise");
+        let (mut agent, mut spawner) = match (agent, make_spawner(&args)) {
+            (Ok(agent), Ok(spawner)) => (agent, spawner),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
         for _ in 0..n {
-            while run {
-                //let mut ai = AI::new(&game.get_board(), 6);
-                let mut ai = AI::new(&game.get_board(), 6);
-                ai.build_tree();
-                let minimax = ai.minimax(Some(Box::new(move |e: &Board| {
-                    let mut rng = thread_rng();
-                    rng.gen_range(-10f64, 10f64)
-                })));
-                if game.step(minimax.get_direction()) == false || game.has_won() {
-                    won = game.has_won();
-                    run = false;
-                }
-                steps += 1;
+            let mut game = new_game().unwrap();
+            let start = Instant::now();
+            while !game.is_over() {
+                match agent.choose_move(&game) {
+                    Some(dir) => game.step_with(dir, spawner.as_mut()),
+                    None => break,
+                };
             }
-            if won {
+            results.push(GameResult::new(
+                &game,
+                "rand",
+                agent.name(),
+                start.elapsed(),
+            ));
+            if game.status() == GameStatus::Won {
+                wins += 1;
                 scores.push(1f64);
             } else {
+                losses += 1;
                 scores.push(game.get_score() as f64 / 3072f64);
             }
         }
+        record_stats(&args, &results);
         println!(
-            "{} avg after {} games",
+            "{} avg after {} games, {} won, {} lost",
             scores.iter().sum::<f64>() / scores.len() as f64,
-            scores.len()
+            scores.len(),
+            wins,
+            losses
         );
+    } else if target == "stats" {
+        let results = match stats_path(&args).and_then(|path| stats::load(&path)) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("failed to read the statistics: {}", e);
+                return;
+            }
+        };
+        let rules: Option<String> = get_option(&args, "--rules");
+        let results: Vec<GameResult> = results
+            .into_iter()
+            .filter(|result| rules.as_ref().map_or(true, |rules| result.rules == *rules))
+            .collect();
+        if results.is_empty() {
+            println!("no games recorded");
+            return;
+        }
+        let groups = stats::summarize(&results);
+        for (group, summary) in groups.iter() {
+            println!("{}\t{}", group, summary);
+        }
+        let top = get_option(&args, "--top").unwrap_or(10);
+        for group in groups.keys() {
+            println!();
+            println!("{}", group);
+            for (i, result) in stats::high_scores(&results, group, top).iter().enumerate() {
+                println!("{}.\t{}", i + 1, result);
+            }
+        }
     }
 }
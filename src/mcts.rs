@@ -0,0 +1,503 @@
+use rand::{prelude::SliceRandom, thread_rng, Rng};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::ai::{AIScore, MinMaxResult, PvStep, SearchStats, Searcher};
+use crate::game::{Board, Cell, Direction};
+
+type NodeId = usize;
+
+/// How moves are picked while playing out a position after it has been added to the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rollout {
+    Random,
+    /// The move whose slide scores best on the heuristic.
+    Greedy,
+}
+
+impl FromStr for Rollout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rollout, String> {
+        match s.trim().to_lowercase().as_str() {
+            "random" => Ok(Rollout::Random),
+            "greedy" => Ok(Rollout::Greedy),
+            _ => Err(format!("unknown rollout policy '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MctsConfig {
+    pub budget: Budget,
+    pub rollout: Rollout,
+    /// Moves played per rollout before it is scored, rollouts also stop when the game is lost.
+    pub rollout_depth: u32,
+    pub exploration: f64,
+}
+
+impl Default for MctsConfig {
+    fn default() -> MctsConfig {
+        MctsConfig {
+            budget: Budget::Iterations(2000),
+            rollout: Rollout::Random,
+            rollout_depth: 50,
+            exploration: 2f64.sqrt(),
+        }
+    }
+}
+
+enum Kind {
+    /// The player picks a direction.
+    Decision,
+    /// The board has slid in the direction, waiting for the tile to spawn.
+    Chance(Direction),
+}
+
+struct MctsNode {
+    board: Board,
+    kind: Kind,
+    spawn: Option<(usize, usize)>,
+    children: Vec<NodeId>,
+    untried: Vec<Direction>,
+    visits: u32,
+    total: f64,
+}
+
+impl MctsNode {
+    fn new(board: Board, kind: Kind) -> MctsNode {
+        let untried = match kind {
+            Kind::Decision => board.legal_moves(),
+            Kind::Chance(_) => Vec::new(),
+        };
+        MctsNode {
+            board,
+            kind,
+            spawn: None,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            total: 0.0,
+        }
+    }
+
+    fn mean(&self) -> f64 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.total / self.visits as f64
+        }
+    }
+
+    fn step(&self) -> Option<PvStep> {
+        match (&self.kind, self.spawn) {
+            (Kind::Chance(dir), _) => Some(PvStep::Move(*dir)),
+            (Kind::Decision, Some((x, y))) => {
+                let tile = self.board.get_cell(x, y).get_score();
+                let tile = tile.map_or(0, |tile| self.board.rules().tile_value(tile));
+                Some(PvStep::Spawn(x, y, tile))
+            }
+            (Kind::Decision, None) => None,
+        }
+    }
+}
+
+/// Sum of the tile values, every spawn adds to it so this rewards surviving long.
+fn tile_sum(board: &Board) -> f64 {
+    board
+        .board_data()
+        .iter()
+        .filter_map(|cell| cell.get_score())
+        .map(|score| board.rules().tile_value(score) as f64)
+        .sum()
+}
+
+/// UCT search, spawns are chance nodes sampled uniformly over the free spawn cells with the
+/// rules' spawn chances.
+/// Like `AI` the tree lives in an arena and the played subtree is kept between moves.
+pub struct Mcts {
+    board: Board,
+    config: MctsConfig,
+    nodes: Vec<MctsNode>,
+    root: Option<NodeId>,
+    stats: SearchStats,
+    reused: usize,
+    min_reward: f64,
+    max_reward: f64,
+}
+
+impl Mcts {
+    pub fn new(board: &Board, config: MctsConfig) -> Mcts {
+        Mcts {
+            board: board.clone(),
+            config,
+            nodes: Vec::new(),
+            root: None,
+            stats: SearchStats::default(),
+            reused: 0,
+            min_reward: 0.0,
+            max_reward: 0.0,
+        }
+    }
+
+    pub fn build_tree(&mut self) {
+        self.reused = 0;
+        self.nodes.clear();
+        self.nodes
+            .push(MctsNode::new(self.board.clone(), Kind::Decision));
+        self.stats.nodes_created += 1;
+        self.root = Some(0);
+    }
+
+    pub fn set_board(&mut self, board: &Board) {
+        if self.root.is_some() && self.board == *board {
+            return;
+        }
+        self.board = board.clone();
+        let found = self.root.and_then(|root| {
+            self.nodes[root]
+                .children
+                .iter()
+                .flat_map(|&child| self.nodes[child].children.iter())
+                .cloned()
+                .find(|&grandchild| self.nodes[grandchild].board == *board)
+        });
+        match found {
+            Some(id) => self.reroot(id),
+            None => self.build_tree(),
+        }
+    }
+
+    /// Like `AI::advance`.
+    pub fn advance(&mut self, dir: Direction, spawn: Option<((usize, usize), u32)>) -> bool {
+        let found = self.root.and_then(|root| {
+            let moved = self.nodes[root].children.iter().cloned().find(|&child| {
+                match self.nodes[child].kind {
+                    Kind::Chance(d) => d == dir,
+                    Kind::Decision => false,
+                }
+            })?;
+            self.nodes[moved]
+                .children
+                .iter()
+                .cloned()
+                .find(|&grandchild| {
+                    let node = &self.nodes[grandchild];
+                    match spawn {
+                        Some(((x, y), tile)) => {
+                            node.spawn == Some((x, y))
+                                && node.board.get_cell(x, y).get_score() == Some(tile)
+                        }
+                        None => node.spawn.is_none(),
+                    }
+                })
+        });
+        match found {
+            Some(id) => {
+                self.reroot(id);
+                true
+            }
+            None => {
+                let mut board = self.board.clone();
+                board.step_rows(dir);
+                if let Some(((x, y), tile)) = spawn {
+                    board.get_mut_cell(x, y).set_score(tile);
+                }
+                self.board = board;
+                self.build_tree();
+                false
+            }
+        }
+    }
+
+    /// Compacts the arena down to the subtree under `id`, visit counts are kept.
+    fn reroot(&mut self, id: NodeId) {
+        let mut mapping = vec![None; self.nodes.len()];
+        let mut order = vec![id];
+        mapping[id] = Some(0);
+        let mut i = 0;
+        while i < order.len() {
+            for &child in self.nodes[order[i]].children.iter() {
+                mapping[child] = Some(order.len());
+                order.push(child);
+            }
+            i += 1;
+        }
+
+        let mut old: Vec<Option<MctsNode>> = self.nodes.drain(..).map(Some).collect();
+        for &old_id in order.iter() {
+            let mut node = old[old_id].take().unwrap();
+            for child in node.children.iter_mut() {
+                *child = mapping[*child].unwrap();
+            }
+            self.nodes.push(node);
+        }
+        self.nodes[0].spawn = None;
+        self.board = self.nodes[0].board.clone();
+        self.root = Some(0);
+        self.reused = self.nodes.len();
+    }
+
+    fn add_node(&mut self, node: MctsNode) -> NodeId {
+        self.stats.nodes_created += 1;
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn tree_memory(&self) -> usize {
+        let cells = self.board.width() * self.board.height();
+        self.nodes.capacity() * mem::size_of::<MctsNode>()
+            + self.nodes.len() * (cells * mem::size_of::<Cell>() + mem::size_of::<NodeId>())
+    }
+
+    pub fn search(&mut self) -> MinMaxResult {
+        if self.root.is_none() {
+            self.build_tree();
+        }
+        self.stats.nodes_reused = self.reused;
+        self.reused = 0;
+        let root = self.root.unwrap();
+
+        let start = Instant::now();
+        let mut iterations = 0;
+        loop {
+            let done = match self.config.budget {
+                Budget::Iterations(n) => iterations >= n,
+                Budget::Time(limit) => iterations > 0 && start.elapsed() >= limit,
+            };
+            let stuck = self.nodes[root].untried.is_empty() && self.nodes[root].children.is_empty();
+            if done || stuck {
+                break;
+            }
+            self.iterate(root);
+            iterations += 1;
+        }
+
+        let mut stats = mem::replace(&mut self.stats, SearchStats::default());
+        stats.searches = 1;
+        stats.tree_nodes = self.nodes.len();
+        stats.peak_memory = self.tree_memory();
+        self.result(root, stats)
+    }
+
+    /// One selection, expansion, rollout and backup pass from the root.
+    fn iterate(&mut self, root: NodeId) {
+        let mut path = vec![root];
+        let mut id = root;
+        self.stats.visit(0);
+        loop {
+            let (next, expanded) = match self.nodes[id].kind {
+                Kind::Decision => match self.nodes[id].untried.pop() {
+                    Some(dir) => (self.expand(id, dir), false),
+                    None if self.nodes[id].children.is_empty() => break,
+                    None => (self.select(id), false),
+                },
+                Kind::Chance(_) => self.sample_spawn(id),
+            };
+            self.stats.visit(path.len() as u32);
+            path.push(next);
+            id = next;
+            if expanded {
+                break;
+            }
+        }
+
+        let reward = self.rollout(&self.nodes[id].board);
+        self.stats.leaf_evaluations += 1;
+        if self.nodes[root].visits == 0 {
+            self.min_reward = reward;
+            self.max_reward = reward;
+        } else {
+            self.min_reward = self.min_reward.min(reward);
+            self.max_reward = self.max_reward.max(reward);
+        }
+        for id in path {
+            self.nodes[id].visits += 1;
+            self.nodes[id].total += reward;
+        }
+    }
+
+    fn expand(&mut self, id: NodeId, dir: Direction) -> NodeId {
+        let mut board = self.nodes[id].board.clone();
+        board.step_rows(dir);
+        let child = self.add_node(MctsNode::new(board, Kind::Chance(dir)));
+        self.nodes[id].children.push(child);
+        child
+    }
+
+    /// Child of a decision node with the highest upper confidence bound, rewards are scaled
+    /// to the range seen so far so the exploration constant doesn't depend on the board.
+    fn select(&self, id: NodeId) -> NodeId {
+        let node = &self.nodes[id];
+        let range = self.max_reward - self.min_reward;
+        let log_visits = (node.visits.max(1) as f64).ln();
+        let ucb = |child: NodeId| {
+            let child = &self.nodes[child];
+            let mean = if range > 0.0 {
+                (child.mean() - self.min_reward) / range
+            } else {
+                0.0
+            };
+            mean + self.config.exploration * (log_visits / child.visits.max(1) as f64).sqrt()
+        };
+        node.children
+            .iter()
+            .cloned()
+            .max_by(|&a, &b| ucb(a).partial_cmp(&ucb(b)).unwrap())
+            .unwrap()
+    }
+
+    /// Draws the spawn for a chance node, returns the child and whether it was just created.
+    fn sample_spawn(&mut self, id: NodeId) -> (NodeId, bool) {
+        let dir = match self.nodes[id].kind {
+            Kind::Chance(dir) => dir,
+            Kind::Decision => unreachable!(),
+        };
+        let mut board = self.nodes[id].board.clone();
+        let spawn = board.step_add(dir);
+
+        if let Some(&child) = self.nodes[id]
+            .children
+            .iter()
+            .find(|&&child| self.nodes[child].board == board)
+        {
+            return (child, false);
+        }
+        let mut node = MctsNode::new(board, Kind::Decision);
+        node.spawn = spawn;
+        let child = self.add_node(node);
+        self.nodes[id].children.push(child);
+        (child, true)
+    }
+
+    fn rollout(&self, board: &Board) -> f64 {
+        let mut board = board.clone();
+        let mut rng = thread_rng();
+        for _ in 0..self.config.rollout_depth {
+            let moves = board.legal_moves();
+            let dir = match self.config.rollout {
+                Rollout::Random => moves.choose(&mut rng).cloned(),
+                Rollout::Greedy => {
+                    let scored: Vec<(Direction, f64)> = moves
+                        .iter()
+                        .map(|&dir| {
+                            let mut board = board.clone();
+                            board.step_rows(dir);
+                            // A little noise so ties don't always go the same way.
+                            (dir, board.get_ai_score() + rng.gen::<f64>())
+                        })
+                        .collect();
+                    scored
+                        .iter()
+                        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                        .map(|&(dir, _)| dir)
+                }
+            };
+            match dir {
+                Some(dir) => board.step(dir),
+                None => break,
+            };
+        }
+        tile_sum(&board)
+    }
+
+    /// The most visited move at every decision and the most visited spawn at every chance node.
+    fn result(&self, root: NodeId, stats: SearchStats) -> MinMaxResult {
+        let most_visited = |id: NodeId| {
+            self.nodes[id]
+                .children
+                .iter()
+                .cloned()
+                .max_by_key(|&child| self.nodes[child].visits)
+        };
+        let root_scores = self.nodes[root]
+            .children
+            .iter()
+            .filter_map(|&child| match self.nodes[child].kind {
+                Kind::Chance(dir) => Some((dir, self.nodes[child].mean())),
+                Kind::Decision => None,
+            })
+            .collect();
+        let mut pv = Vec::new();
+        let mut score = self.nodes[root].mean();
+        let mut id = root;
+        while let Some(child) = most_visited(id) {
+            if id == root {
+                score = self.nodes[child].mean();
+            }
+            if let Some(step) = self.nodes[child].step() {
+                pv.push(step);
+            }
+            id = child;
+        }
+        MinMaxResult::from_parts(score, pv, root_scores, stats)
+    }
+
+    /// Writes the tree up to `max_depth` layers as a Graphviz digraph, labelled with visit
+    /// counts and mean rewards. Boxes are decisions, ellipses chance nodes.
+    pub fn export_dot<P: AsRef<Path>>(&self, path: P, max_depth: u32) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "digraph search {{")?;
+        writeln!(out, "    node [fontname=\"monospace\"];")?;
+        if let Some(root) = self.root {
+            let mut stack = vec![(root, 0)];
+            while let Some((id, layer)) = stack.pop() {
+                let node = &self.nodes[id];
+                let (shape, kind) = match node.kind {
+                    Kind::Decision => ("box", "move"),
+                    Kind::Chance(_) => ("ellipse", "spawn"),
+                };
+                writeln!(
+                    out,
+                    "    n{} [shape={}, label=\"{}\\l{}\\ln={} v={:.1}\\l\"];",
+                    id,
+                    shape,
+                    kind,
+                    node.board.to_string().replace('\n', "\\l"),
+                    node.visits,
+                    node.mean()
+                )?;
+                if layer < max_depth {
+                    for &child in node.children.iter() {
+                        let label = self.nodes[child]
+                            .step()
+                            .map_or(String::new(), |step| step.to_string());
+                        writeln!(out, "    n{} -> n{} [label=\"{}\"];", id, child, label)?;
+                        stack.push((child, layer + 1));
+                    }
+                }
+            }
+        }
+        writeln!(out, "}}")?;
+        out.flush()
+    }
+}
+
+impl Searcher for Mcts {
+    fn think(&mut self) -> MinMaxResult {
+        self.search()
+    }
+
+    fn set_board(&mut self, board: &Board) {
+        Mcts::set_board(self, board)
+    }
+
+    fn advance(&mut self, dir: Direction, spawn: Option<((usize, usize), u32)>) -> bool {
+        Mcts::advance(self, dir, spawn)
+    }
+
+    fn export_dot(&self, path: &Path, max_depth: u32) -> io::Result<()> {
+        Mcts::export_dot(self, path, max_depth)
+    }
+}
@@ -0,0 +1,175 @@
+use rand::{prelude::SliceRandom, thread_rng};
+
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+
+use crate::ai::{AIScore, MinMaxResult, PvStep, SearchStats, Searcher};
+use crate::game::{Board, Direction, Game};
+
+/// What a finished rollout is worth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RolloutScore {
+    /// `Game::get_score` of the final position.
+    Score,
+    /// Moves played before the game was lost or the depth cap was hit.
+    Moves,
+    /// The handcrafted evaluator on the final board.
+    Heuristic,
+}
+
+impl FromStr for RolloutScore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RolloutScore, String> {
+        match s.trim().to_lowercase().as_str() {
+            "score" => Ok(RolloutScore::Score),
+            "moves" => Ok(RolloutScore::Moves),
+            "heuristic" => Ok(RolloutScore::Heuristic),
+            _ => Err(format!("unknown rollout scoring '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MonteCarloConfig {
+    pub rollouts: u32,
+    /// Moves per rollout, `None` plays until the game is lost.
+    pub depth: Option<u32>,
+    pub scoring: RolloutScore,
+    pub threads: usize,
+}
+
+impl Default for MonteCarloConfig {
+    fn default() -> MonteCarloConfig {
+        MonteCarloConfig {
+            rollouts: 100,
+            depth: None,
+            scoring: RolloutScore::Score,
+            threads: 4,
+        }
+    }
+}
+
+/// Plays `dir` followed by random moves and scores where the game ends up.
+fn rollout(board: &Board, dir: Direction, config: &MonteCarloConfig) -> f64 {
+    let mut game = Game::with_target(11, true);
+    game.set_board(board.clone());
+    game.step(dir);
+    let mut rng = thread_rng();
+    let mut moves = 1;
+    while config.depth.map_or(true, |depth| moves < depth) {
+        let dir = match game.get_board().legal_moves().choose(&mut rng) {
+            Some(&dir) => dir,
+            None => break,
+        };
+        game.step(dir);
+        moves += 1;
+    }
+    match config.scoring {
+        RolloutScore::Score => game.get_score() as f64,
+        RolloutScore::Moves => moves as f64,
+        RolloutScore::Heuristic => game.get_board().get_ai_score(),
+    }
+}
+
+/// Picks the move with the best average over `rollouts` random games, the rollouts of every
+/// move are split over `threads` worker threads.
+pub struct MonteCarlo {
+    board: Board,
+    config: MonteCarloConfig,
+}
+
+impl MonteCarlo {
+    pub fn new(board: &Board, config: MonteCarloConfig) -> MonteCarlo {
+        MonteCarlo {
+            board: board.clone(),
+            config,
+        }
+    }
+
+    pub fn search(&self) -> MinMaxResult {
+        let moves = self.board.legal_moves();
+        let threads = self.config.threads.max(1);
+        let workers: Vec<_> = (0..threads)
+            .map(|i| {
+                let board = self.board.clone();
+                let moves = moves.clone();
+                let config = self.config;
+                // Spread the remainder so exactly `rollouts` games are played per move.
+                let count = config.rollouts / threads as u32
+                    + if (i as u32) < config.rollouts % threads as u32 {
+                        1
+                    } else {
+                        0
+                    };
+                thread::spawn(move || {
+                    moves
+                        .iter()
+                        .map(|&dir| (0..count).map(|_| rollout(&board, dir, &config)).sum())
+                        .collect::<Vec<f64>>()
+                })
+            })
+            .collect();
+
+        let mut totals = vec![0.0; moves.len()];
+        for worker in workers {
+            let sums = worker.join().expect("rollout thread panicked");
+            for (total, sum) in totals.iter_mut().zip(sums) {
+                *total += sum;
+            }
+        }
+
+        let rollouts = self.config.rollouts.max(1) as f64;
+        let root_scores: Vec<(Direction, f64)> = moves
+            .iter()
+            .cloned()
+            .zip(totals.iter().map(|total| total / rollouts))
+            .collect();
+        let best = root_scores
+            .iter()
+            .cloned()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let mut stats = SearchStats::default();
+        stats.searches = 1;
+        stats.visit(0);
+        for _ in 0..moves.len() {
+            stats.visit(1);
+        }
+        stats.leaf_evaluations = moves.len() * self.config.rollouts as usize;
+        match best {
+            Some((dir, score)) => {
+                MinMaxResult::from_parts(score, vec![PvStep::Move(dir)], root_scores, stats)
+            }
+            None => MinMaxResult::from_parts(0.0, Vec::new(), root_scores, stats),
+        }
+    }
+}
+
+impl Searcher for MonteCarlo {
+    fn think(&mut self) -> MinMaxResult {
+        self.search()
+    }
+
+    fn set_board(&mut self, board: &Board) {
+        self.board = board.clone();
+    }
+
+    /// Nothing is kept between moves, so this only replays the move on the stored board.
+    fn advance(&mut self, dir: Direction, spawn: Option<((usize, usize), u32)>) -> bool {
+        self.board.step_rows(dir);
+        if let Some(((x, y), tile)) = spawn {
+            self.board.get_mut_cell(x, y).set_score(tile);
+        }
+        false
+    }
+
+    fn export_dot(&self, _path: &Path, _max_depth: u32) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "the monte carlo agent keeps no search tree",
+        ))
+    }
+}
@@ -0,0 +1,249 @@
+//! Game records, a starting board followed by every move and where its tile spawned:
+//!
+//! ```text
+//! rules threes
+//! target 768
+//! endless
+//! board 1.0./..2./.1../0..2
+//! left 2 1
+//! up -
+//! ```
+//!
+//! Spawns are the index among the spawn cells as taken by `Game::spawn`, followed by the tile
+//! when it isn't tile 0, or `-` when no spawn cell was free. The `rules` line defaults to the edge
+//! spawn rules, `endless` is optional and lines starting with `#` are ignored.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::game::{Board, Direction, Game, Notation};
+use crate::rules::{self, Ruleset};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Record {
+    target: u32,
+    endless: bool,
+    start: Board,
+    /// Each move with the index and tile of its spawn.
+    moves: Vec<(Direction, Option<(usize, u32)>)>,
+}
+
+impl Record {
+    /// Starts recording from the current position of `game`.
+    pub fn new(game: &Game) -> Record {
+        Record {
+            target: game.target(),
+            endless: game.is_endless(),
+            start: game.get_board().clone(),
+            moves: Vec::new(),
+        }
+    }
+
+    /// Adds `dir` after `game` has finished playing it.
+    pub fn push(&mut self, dir: Direction, game: &Game) {
+        let board = game.get_board();
+        let spawn = game.last_spawn().and_then(|(x, y)| {
+            let index = board
+                .spawn_cells(dir)
+                .iter()
+                .position(|&cell| cell == (x, y))?;
+            Some((index, board.get_cell(x, y).get_score().unwrap_or(0)))
+        });
+        self.moves.push((dir, spawn));
+    }
+
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    pub fn rules(&self) -> &'static dyn Ruleset {
+        self.start.rules()
+    }
+
+    pub fn moves(&self) -> &[(Direction, Option<(usize, u32)>)] {
+        &self.moves
+    }
+
+    /// Plays the record from its starting board, failing on the first move that is illegal or
+    /// whose spawn doesn't fit the position.
+    pub fn replay(&self) -> Result<Game, String> {
+        let mut game = Game::with_rules(self.rules(), self.target, self.endless);
+        game.set_board(self.start.clone());
+        for (i, &(dir, spawn)) in self.moves.iter().enumerate() {
+            if !game.slide(dir) {
+                return Err(format!("move {} ({}) is illegal", i + 1, dir));
+            }
+            match (game.pending_spawn(), spawn) {
+                (Some(_), Some((index, tile))) => {
+                    if !game.spawn_tile(index, tile) {
+                        return Err(format!(
+                            "move {} ({}) spawns on {}, which isn't free",
+                            i + 1,
+                            dir,
+                            index
+                        ));
+                    }
+                }
+                (Some(_), None) => {
+                    return Err(format!("move {} ({}) is missing its spawn", i + 1, dir));
+                }
+                (None, Some(_)) => {
+                    return Err(format!("move {} ({}) spawns on a full edge", i + 1, dir));
+                }
+                (None, None) => {}
+            }
+        }
+        Ok(game)
+    }
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Record, String> {
+        let mut rules: &'static dyn Ruleset = rules::by_name("edge")?;
+        let mut target = None;
+        let mut endless = false;
+        let mut start = None;
+        let mut moves = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: String| format!("line {}: {}", i + 1, e);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["rules", name] if start.is_none() => {
+                    rules = rules::by_name(name).map_err(error)?
+                }
+                ["target", value] => match value.parse::<u64>() {
+                    Ok(value) => target = Some(value),
+                    Err(_) => return Err(error(format!("invalid target '{}'", value))),
+                },
+                ["endless"] => endless = true,
+                ["board", board] => {
+                    let board = Board::parse(board, Notation::Compact).map_err(error)?;
+                    start = Some(board.with_rules(rules));
+                }
+                [dir, spawn @ ..] if start.is_some() && spawn.len() <= 2 => {
+                    let dir: Direction = dir.parse().map_err(error)?;
+                    let number = |word: &str| {
+                        word.parse()
+                            .map_err(|_| error(format!("invalid spawn '{}'", line)))
+                    };
+                    let spawn = match spawn {
+                        ["-"] => None,
+                        [index] => Some((number(index)?, 0)),
+                        [index, tile] => {
+                            let tile: u32 = tile
+                                .parse()
+                                .map_err(|_| error(format!("invalid spawn '{}'", line)))?;
+                            if rules.tile_code(rules.tile_value(tile)) != Some(tile) {
+                                return Err(error(format!("invalid tile in '{}'", line)));
+                            }
+                            Some((number(index)?, tile))
+                        }
+                        _ => return Err(error(format!("missing spawn in '{}'", line))),
+                    };
+                    moves.push((dir, spawn));
+                }
+                _ => return Err(error(format!("unexpected '{}'", line))),
+            }
+        }
+        let target = target.ok_or("record has no target")?;
+        Ok(Record {
+            target: rules
+                .tile_code(target)
+                .ok_or_else(|| format!("invalid target '{}'", target))?,
+            endless,
+            start: start.ok_or("record has no board")?,
+            moves,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rules().name() != "edge" {
+            writeln!(f, "rules {}", self.rules().name())?;
+        }
+        writeln!(f, "target {}", self.rules().tile_value(self.target))?;
+        if self.endless {
+            writeln!(f, "endless")?;
+        }
+        writeln!(f, "board {}", self.start.to_notation(Notation::Compact))?;
+        for &(dir, spawn) in self.moves.iter() {
+            match spawn {
+                Some((index, 0)) => writeln!(f, "{} {}", dir, index)?,
+                Some((index, tile)) => writeln!(f, "{} {} {}", dir, index, tile)?,
+                None => writeln!(f, "{} -", dir)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::spawner::RandomSpawner;
+
+    #[test]
+    fn recorded_games_replay_to_the_same_position() {
+        for i in 0..20 {
+            let rules = rules::by_name(rules::RULESETS[i % rules::RULESETS.len()]).unwrap();
+            let mut game = Game::with_rules(rules, 11, true);
+            let mut record = Record::new(&game);
+            let mut spawner = RandomSpawner;
+            while let Some(&dir) = game.get_board().legal_moves().first() {
+                game.step_with(dir, &mut spawner);
+                record.push(dir, &game);
+            }
+            let loaded: Record = record.to_string().parse().unwrap();
+            assert_eq!(loaded, record);
+            let replayed = loaded.replay().unwrap();
+            assert_eq!(replayed.get_board(), game.get_board());
+            assert_eq!(replayed.moves(), game.moves());
+            assert_eq!(replayed.status(), game.status());
+        }
+    }
+
+    #[test]
+    fn invalid_records_are_rejected() {
+        let invalid = [
+            "",
+            "target 2048",
+            "target 3\nboard 0",
+            "target 2048\nboard 0/",
+            "target 2048\nleft 0\nboard 0.",
+            "target 2048\nboard 0.\nleft",
+            "target 2048\nboard 0.\nsideways 0",
+            "target 2048\nboard 0.\nleft x",
+            "target 2048\nboard 0.\nleft 0 1 2",
+            "target 2048\nboard 0.\nleft 0 70",
+            "target 2048\nboard 0.\nleft 0 4294967297",
+            "rules threes\ntarget 768\nboard 0.\nleft 0 70",
+            "target 2048\nboard 0.\nrules threes",
+            "rules threes\ntarget 2048\nboard 0.",
+            "rules chess\ntarget 2048\nboard 0.",
+        ];
+        for s in invalid.iter() {
+            assert!(s.parse::<Record>().is_err(), "{:?}", s);
+        }
+
+        let replays = [
+            // Nothing moves.
+            "target 2048\nboard .0\nright -",
+            // The spawn edge of a move left is the right column, its top cell is taken.
+            "target 2048\nboard 01/.0\nleft 0",
+            "target 2048\nboard 01/.0\nleft 5",
+            "target 2048\nboard 01/.0\nleft -",
+        ];
+        for s in replays.iter() {
+            let record: Record = s.parse().unwrap();
+            assert!(record.replay().is_err(), "{:?}", s);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Differential tests: every board implementation is run against a deliberately naive
+//! reference on random positions and on the golden rows in `tests/golden/rows.txt`.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::env;
+
+use crate::game::tests::random_board;
+use crate::game::{Board, Direction, Notation};
+use crate::symmetry::Transform;
+
+/// Slides a board, returning the result and whether anything moved.
+pub type StepFn = fn(&Board, Direction) -> (Board, bool);
+
+/// Implementations checked against the reference, alternatives are added here.
+pub const IMPLEMENTATIONS: [(&str, StepFn); 1] = [("board", board_step)];
+
+const GOLDEN_ROWS: &str = include_str!("../tests/golden/rows.txt");
+
+fn board_step(board: &Board, dir: Direction) -> (Board, bool) {
+    let mut board = board.clone();
+    let moved = board.step_rows(dir);
+    (board, moved)
+}
+
+/// Packs the tiles of a line towards its start, merging each equal pair once.
+fn reference_line(line: &[Option<u32>]) -> Vec<Option<u32>> {
+    let tiles: Vec<u32> = line.iter().filter_map(|&cell| cell).collect();
+    let mut result = Vec::with_capacity(line.len());
+    let mut i = 0;
+    while i < tiles.len() {
+        if i + 1 < tiles.len() && tiles[i] == tiles[i + 1] {
+            result.push(Some(tiles[i] + 1));
+            i += 2;
+        } else {
+            result.push(Some(tiles[i]));
+            i += 1;
+        }
+    }
+    result.resize(line.len(), None);
+    result
+}
+
+/// Cell positions of every line a move in `dir` packs, ordered from the edge tiles move to.
+fn lines(board: &Board, dir: Direction) -> Vec<Vec<(usize, usize)>> {
+    let (width, height) = (board.width(), board.height());
+    match dir {
+        Direction::LEFT => (0..height)
+            .map(|y| (0..width).map(|x| (x, y)).collect())
+            .collect(),
+        Direction::RIGHT => (0..height)
+            .map(|y| (0..width).rev().map(|x| (x, y)).collect())
+            .collect(),
+        Direction::UP => (0..width)
+            .map(|x| (0..height).map(|y| (x, y)).collect())
+            .collect(),
+        Direction::DOWN => (0..width)
+            .map(|x| (0..height).rev().map(|y| (x, y)).collect())
+            .collect(),
+    }
+}
+
+/// Packs every line, blocked cells cut a line into segments that are packed on their own.
+pub fn reference_step(board: &Board, dir: Direction) -> (Board, bool) {
+    let mut result = board.clone();
+    for line in lines(board, dir) {
+        for segment in line.split(|&(x, y)| board.get_cell(x, y).is_blocked()) {
+            let cells: Vec<Option<u32>> = segment
+                .iter()
+                .map(|&(x, y)| board.get_cell(x, y).get_score())
+                .collect();
+            for (&(x, y), cell) in segment.iter().zip(reference_line(&cells)) {
+                match cell {
+                    Some(tile) => result.get_mut_cell(x, y).set_score(tile),
+                    None => result.get_mut_cell(x, y).set_none(),
+                }
+            }
+        }
+    }
+    let moved = result != *board;
+    (result, moved)
+}
+
+/// Checks every implementation on one position, describing the first divergence.
+pub fn compare(board: &Board, dir: Direction) -> Result<(), String> {
+    let (expected, expected_moved) = reference_step(board, dir);
+    for &(name, step) in IMPLEMENTATIONS.iter() {
+        let (result, moved) = step(board, dir);
+        if result != expected || moved != expected_moved {
+            return Err(format!(
+                "{} diverges moving {} on {}: expected {} (moved: {}), got {} (moved: {})",
+                name,
+                dir,
+                board.to_notation(Notation::Compact),
+                expected.to_notation(Notation::Compact),
+                expected_moved,
+                result.to_notation(Notation::Compact),
+                moved
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs `cases` random positions from `seed`, mixing board sizes and densities. Every other
+/// position has some of its cells blocked.
+pub fn run(seed: u64, cases: usize) -> Result<(), String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for case in 0..cases {
+        let width = rng.gen_range(1, 7);
+        let height = rng.gen_range(1, 7);
+        let empty = rng.gen_range(0.0, 0.8);
+        let mut board = random_board(&mut rng, width, height, empty);
+        if case % 2 == 1 {
+            for y in 0..height {
+                for x in 0..width {
+                    if rng.gen_bool(0.2) {
+                        board.get_mut_cell(x, y).set_blocked();
+                    }
+                }
+            }
+        }
+        for &dir in Direction::all().iter() {
+            compare(&board, dir).map_err(|e| format!("case {} of seed {}: {}", case, seed, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Parses the golden corpus into `(row, expected)` boards of a single row.
+fn golden_rows() -> Vec<(Board, Board)> {
+    GOLDEN_ROWS
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut parts = line.split("->");
+            let mut parse = || {
+                let part = parts.next().expect(line).trim();
+                Board::parse(part, Notation::Compact).expect(line)
+            };
+            (parse(), parse())
+        })
+        .collect()
+}
+
+#[test]
+fn random_positions_match_the_reference() {
+    let cases = env::var("AUTO2048_DIFF_CASES")
+        .ok()
+        .and_then(|cases| cases.parse().ok())
+        .unwrap_or(20_000);
+    run(2048, cases).unwrap();
+}
+
+#[test]
+#[ignore]
+fn millions_of_positions_match_the_reference() {
+    run(4096, 2_000_000).unwrap();
+}
+
+#[test]
+fn golden_rows_match_every_implementation() {
+    let rows = golden_rows();
+    assert!(!rows.is_empty());
+    // The rows are given sliding left, the same rows rotated cover the other directions.
+    let transforms = [
+        Transform::Identity,
+        Transform::FlipHorizontal,
+        Transform::Transpose,
+        Transform::AntiTranspose,
+    ];
+    for (row, expected) in rows.iter() {
+        for &transform in transforms.iter() {
+            let dir = transform.map_direction(Direction::LEFT);
+            let board = row.transformed(transform);
+            let expected = expected.transformed(transform);
+            for &(name, step) in IMPLEMENTATIONS.iter() {
+                let (result, _) = step(&board, dir);
+                assert_eq!(
+                    result.to_notation(Notation::Compact),
+                    expected.to_notation(Notation::Compact),
+                    "{} moving {} on {}",
+                    name,
+                    dir,
+                    board.to_notation(Notation::Compact)
+                );
+            }
+            compare(&board, dir).unwrap();
+        }
+    }
+}
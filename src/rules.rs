@@ -0,0 +1,615 @@
+//! Rulesets decide how lines slide and merge, where and which tiles spawn, what a board scores
+//! and when a game ends. Every board carries the ruleset it is played under, so searches follow
+//! the rules of the board they were given.
+
+use rand::{prelude::SliceRandom, thread_rng, Rng};
+
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+use crate::game::{combine_line_with, merge_equal, Board, Cell, Direction};
+
+/// Names accepted by `by_name`, which also takes `sequence:` followed by the comma separated
+/// values of a custom merge sequence.
+pub const RULESETS: [&str; 4] = ["edge", "classic", "threes", "fibonacci"];
+
+pub trait Ruleset: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Slides `line` towards its first cell, returns whether anything changed. The value of
+    /// every tile a merge makes is added to `score`. Boards cut their lines at blocked cells,
+    /// so `line` never holds a block.
+    fn slide_line(&self, line: &mut [&mut Cell], score: &mut u64) -> bool;
+
+    /// Number shown on a tile.
+    fn tile_value(&self, tile: u32) -> u64;
+
+    /// The tile showing `value`, `None` when no tile does.
+    fn tile_code(&self, value: u64) -> Option<u32>;
+
+    /// Every tile a spawn can be with its chance, searches branch over all of them.
+    fn spawn_tiles(&self) -> Vec<(u32, f64)>;
+
+    /// Number of cells, free or not, a spawn after a move in `dir` can land on. The edge
+    /// opposite the move by default.
+    fn spawn_count(&self, board: &Board, dir: Direction) -> usize {
+        match dir {
+            Direction::UP | Direction::DOWN => board.width(),
+            Direction::LEFT | Direction::RIGHT => board.height(),
+        }
+    }
+
+    /// Position of the `index`th of those cells, spawns are picked by this index. Only called
+    /// with an index below `spawn_count`.
+    fn spawn_cell(&self, board: &Board, dir: Direction, index: usize) -> (usize, usize) {
+        match dir {
+            Direction::UP => (index, board.height() - 1),
+            Direction::DOWN => (index, 0),
+            Direction::LEFT => (board.width() - 1, index),
+            Direction::RIGHT => (0, index),
+        }
+    }
+
+    /// Picks the next tile to spawn on `board`, `deck` is kept by the game between draws.
+    fn draw(&self, _deck: &mut Vec<u32>, _board: &Board) -> u32 {
+        self.spawn_tiles()
+            .choose_weighted(&mut thread_rng(), |&(_, chance)| chance)
+            .map_or(0, |&(tile, _)| tile)
+    }
+
+    /// Whether players see the tile the next spawn places before they move, otherwise it is
+    /// only known to the game.
+    fn previews_next_tile(&self) -> bool {
+        false
+    }
+
+    /// Places the starting tiles on an empty board, a single drawn tile by default.
+    fn start(&self, board: &mut Board, deck: &mut Vec<u32>) {
+        let tile = self.draw(deck, board);
+        board.add_random_tile(tile);
+    }
+
+    /// Score of a game on `board` whose merges scored `merge_score`, see `Game::merge_score`.
+    fn score(&self, board: &Board, merge_score: u64) -> i32;
+
+    /// Whether `board` holds the `target` tile.
+    fn has_won(&self, board: &Board, target: u32) -> bool {
+        board.max_tile().map_or(false, |max| max >= target)
+    }
+
+    /// Whether play has ended on `board`, won or not.
+    fn is_terminal(&self, board: &Board) -> bool {
+        !board.has_moves()
+    }
+
+    /// Target tile of a game when none is picked.
+    fn default_target(&self) -> u32;
+}
+
+pub fn by_name(name: &str) -> Result<&'static dyn Ruleset, String> {
+    match name {
+        "edge" => Ok(&EdgeSpawn),
+        "classic" => Ok(&Classic),
+        "threes" => Ok(&Threes),
+        "fibonacci" => Ok(intern(Sequence::fibonacci())?),
+        _ if name.starts_with("sequence:") => {
+            let values = name["sequence:".len()..]
+                .split(',')
+                .map(|value| {
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("invalid sequence value '{}'", value))
+                })
+                .collect::<Result<Vec<u64>, String>>()?;
+            Ok(intern(Sequence::new(values)?)?)
+        }
+        _ => Err(format!("unknown ruleset '{}'", name)),
+    }
+}
+
+/// Most distinct sequences `intern` hands out, names come from users and the server's clients.
+const MAX_SEQUENCES: usize = 64;
+
+/// Boards refer to their rules, which therefore live as long as the program. Sequences are
+/// built at runtime, each distinct one is leaked once and handed out again after that, up to
+/// `MAX_SEQUENCES` of them.
+fn intern(sequence: Sequence) -> Result<&'static Sequence, String> {
+    static SEQUENCES: Mutex<Vec<&'static Sequence>> = Mutex::new(Vec::new());
+    let mut sequences = SEQUENCES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&known) = sequences.iter().find(|known| known.name == sequence.name) {
+        return Ok(known);
+    }
+    if sequences.len() >= MAX_SEQUENCES {
+        return Err(format!(
+            "no room for '{}', at most {} sequences can be in use",
+            sequence.name, MAX_SEQUENCES
+        ));
+    }
+    let sequence: &'static Sequence = Box::leak(Box::new(sequence));
+    sequences.push(sequence);
+    Ok(sequence)
+}
+
+/// Equal tiles merge into the next power of two and a 1 enters on the edge opposite the move.
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeSpawn;
+
+impl Ruleset for EdgeSpawn {
+    fn name(&self) -> &str {
+        "edge"
+    }
+
+    fn slide_line(&self, line: &mut [&mut Cell], score: &mut u64) -> bool {
+        combine_line_with(line, |a, b| {
+            let merged = merge_equal(a, b)?;
+            *score = score.saturating_add(self.tile_value(merged));
+            Some(merged)
+        })
+    }
+
+    fn tile_value(&self, tile: u32) -> u64 {
+        2u64.checked_pow(tile).unwrap_or(u64::MAX)
+    }
+
+    fn tile_code(&self, value: u64) -> Option<u32> {
+        if value.is_power_of_two() && value < 1 << 32 {
+            Some(value.trailing_zeros())
+        } else {
+            None
+        }
+    }
+
+    fn spawn_tiles(&self) -> Vec<(u32, f64)> {
+        vec![(0, 1.0)]
+    }
+
+    /// Sum of the tiles minus the ones every spawn added, saturating on boards loaded with
+    /// huge tiles.
+    fn score(&self, board: &Board, _merge_score: u64) -> i32 {
+        let mut cells: i32 = 0;
+        let mut score: i32 = 0;
+        for exponent in board
+            .board_data()
+            .iter()
+            .filter_map(|cell| cell.get_score())
+        {
+            cells += 1;
+            score = score.saturating_add(2_i32.checked_pow(exponent).unwrap_or(i32::MAX));
+        }
+        score.saturating_sub(cells).saturating_add(1)
+    }
+
+    fn default_target(&self) -> u32 {
+        11
+    }
+}
+
+/// The original game: equal tiles merge into the next power of two and a 2, or a 4 one time in
+/// ten, spawns on any free cell. Two tiles to start with.
+#[derive(Debug, Clone, Copy)]
+pub struct Classic;
+
+impl Ruleset for Classic {
+    fn name(&self) -> &str {
+        "classic"
+    }
+
+    fn slide_line(&self, line: &mut [&mut Cell], score: &mut u64) -> bool {
+        combine_line_with(line, |a, b| {
+            let merged = merge_equal(a, b)?;
+            *score = score.saturating_add(self.tile_value(merged));
+            Some(merged)
+        })
+    }
+
+    fn tile_value(&self, tile: u32) -> u64 {
+        EdgeSpawn.tile_value(tile)
+    }
+
+    fn tile_code(&self, value: u64) -> Option<u32> {
+        EdgeSpawn.tile_code(value)
+    }
+
+    fn spawn_tiles(&self) -> Vec<(u32, f64)> {
+        vec![(1, 0.9), (2, 0.1)]
+    }
+
+    /// Every cell, row by row.
+    fn spawn_count(&self, board: &Board, _dir: Direction) -> usize {
+        board.width() * board.height()
+    }
+
+    fn spawn_cell(&self, board: &Board, _dir: Direction, index: usize) -> (usize, usize) {
+        (index % board.width(), index / board.width())
+    }
+
+    fn start(&self, board: &mut Board, deck: &mut Vec<u32>) {
+        for _ in 0..2 {
+            let tile = self.draw(deck, board);
+            board.add_random_tile(tile);
+        }
+    }
+
+    /// The merge score, like the original game keeps.
+    fn score(&self, _board: &Board, merge_score: u64) -> i32 {
+        merge_score.min(i32::MAX as u64) as i32
+    }
+
+    fn default_target(&self) -> u32 {
+        11
+    }
+}
+
+/// Threes!: a 1 and a 2 make a 3, equal tiles from 3 up double, and every tile moves at most a
+/// single cell per move. Tiles are drawn from a shuffled deck of four 1s, 2s and 3s, with the
+/// odd bonus tile once the board holds a 48. New tiles enter on a free cell of the edge
+/// opposite the move, like in `EdgeSpawn`.
+///
+/// Tile 0 is a 1, tile 1 a 2 and tile `n` from 2 up is `3 * 2^(n - 2)`.
+#[derive(Debug, Clone, Copy)]
+pub struct Threes;
+
+impl Threes {
+    fn merge(a: u32, b: u32) -> Option<u32> {
+        match (a, b) {
+            (0, 1) | (1, 0) => Some(2),
+            (a, b) if a == b && a >= 2 => Some(a + 1),
+            _ => None,
+        }
+    }
+}
+
+/// Moves every cell after `from` one place towards the start of `line`.
+fn shift(line: &mut [&mut Cell], from: usize) {
+    for i in from..line.len() - 1 {
+        match line[i + 1].get_score() {
+            Some(score) => line[i].set_score(score),
+            None => line[i].set_none(),
+        }
+    }
+    line[line.len() - 1].set_none();
+}
+
+impl Ruleset for Threes {
+    fn name(&self) -> &str {
+        "threes"
+    }
+
+    fn previews_next_tile(&self) -> bool {
+        true
+    }
+
+    fn slide_line(&self, line: &mut [&mut Cell], score: &mut u64) -> bool {
+        for i in 0..line.len().saturating_sub(1) {
+            match (line[i].get_score(), line[i + 1].get_score()) {
+                (None, _) => {
+                    if line[i + 1..].iter().any(|cell| cell.is_set()) {
+                        shift(line, i);
+                        return true;
+                    }
+                    return false;
+                }
+                (Some(a), Some(b)) => {
+                    if let Some(merged) = Threes::merge(a, b) {
+                        line[i].set_score(merged);
+                        shift(line, i + 1);
+                        *score = score.saturating_add(self.tile_value(merged));
+                        return true;
+                    }
+                }
+                (Some(_), None) => {}
+            }
+        }
+        false
+    }
+
+    fn tile_value(&self, tile: u32) -> u64 {
+        match tile {
+            0 => 1,
+            1 => 2,
+            n => 3u64.checked_shl(n - 2).unwrap_or(u64::MAX),
+        }
+    }
+
+    fn tile_code(&self, value: u64) -> Option<u32> {
+        match value {
+            1 => Some(0),
+            2 => Some(1),
+            v if v % 3 == 0 && (v / 3).is_power_of_two() => Some((v / 3).trailing_zeros() + 2),
+            _ => None,
+        }
+    }
+
+    fn spawn_tiles(&self) -> Vec<(u32, f64)> {
+        vec![(0, 1.0 / 3.0), (1, 1.0 / 3.0), (2, 1.0 / 3.0)]
+    }
+
+    fn draw(&self, deck: &mut Vec<u32>, board: &Board) -> u32 {
+        let mut rng = thread_rng();
+        // A bonus tile between 6 and an eighth of the highest tile, once that is 48 or more.
+        if let Some(max) = board.max_tile() {
+            if max >= 6 && rng.gen_range(0, 21) == 0 {
+                return rng.gen_range(3, max - 2);
+            }
+        }
+        if deck.is_empty() {
+            deck.extend([0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2].iter());
+            deck.shuffle(&mut rng);
+        }
+        deck.pop().unwrap()
+    }
+
+    /// Nine tiles from the deck on random cells.
+    fn start(&self, board: &mut Board, deck: &mut Vec<u32>) {
+        for _ in 0..9 {
+            let tile = self.draw(deck, board);
+            board.add_random_tile(tile);
+        }
+    }
+
+    /// Every tile from 3 up scores `3^(n - 1)`, 1s and 2s score nothing.
+    fn score(&self, board: &Board, _merge_score: u64) -> i32 {
+        board
+            .board_data()
+            .iter()
+            .filter_map(|cell| cell.get_score())
+            .filter(|&tile| tile >= 2)
+            .fold(0i32, |score, tile| {
+                score.saturating_add(3_i32.checked_pow(tile - 1).unwrap_or(i32::MAX))
+            })
+    }
+
+    /// 768.
+    fn default_target(&self) -> u32 {
+        10
+    }
+}
+
+/// Tiles are the values of a sequence and two tiles merge when they are neighbours in it, into
+/// the value after the larger one. With the Fibonacci numbers a 2 and a 3 make a 5. Two of the
+/// smallest value merge when they add up to the second one, like the 1s of Fibonacci do. The two
+/// smallest values spawn, on the edge opposite the move like in `EdgeSpawn`.
+///
+/// Tile `n` is the `n`th value of the sequence.
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    name: String,
+    values: Vec<u64>,
+}
+
+impl Sequence {
+    /// At most 32 values, the most a compact board can write down.
+    const MAX_LEN: usize = 32;
+
+    /// Takes a strictly increasing sequence of at least three values.
+    pub fn new(values: Vec<u64>) -> Result<Sequence, String> {
+        if values.len() < 3 || values.len() > Sequence::MAX_LEN {
+            return Err(format!(
+                "a sequence needs 3 to {} values, not {}",
+                Sequence::MAX_LEN,
+                values.len()
+            ));
+        }
+        if values.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("sequence values must be increasing".to_string());
+        }
+        let list: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        Ok(Sequence {
+            name: format!("sequence:{}", list.join(",")),
+            values,
+        })
+    }
+
+    /// 1, 2, 3, 5, 8 and so on.
+    pub fn fibonacci() -> Sequence {
+        let mut values = vec![1, 2];
+        while values.len() < Sequence::MAX_LEN {
+            values.push(values[values.len() - 2] + values[values.len() - 1]);
+        }
+        Sequence {
+            name: "fibonacci".to_string(),
+            values,
+        }
+    }
+
+    fn merge(&self, a: u32, b: u32) -> Option<u32> {
+        if a == 0 && b == 0 {
+            return if self.values[0].checked_mul(2) == Some(self.values[1]) {
+                Some(1)
+            } else {
+                None
+            };
+        }
+        let merged = a.max(b) + 1;
+        if a.max(b) - a.min(b) == 1 && (merged as usize) < self.values.len() {
+            Some(merged)
+        } else {
+            None
+        }
+    }
+}
+
+impl Ruleset for Sequence {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn slide_line(&self, line: &mut [&mut Cell], score: &mut u64) -> bool {
+        combine_line_with(line, |a, b| {
+            let merged = self.merge(a, b)?;
+            *score = score.saturating_add(self.tile_value(merged));
+            Some(merged)
+        })
+    }
+
+    fn tile_value(&self, tile: u32) -> u64 {
+        self.values.get(tile as usize).cloned().unwrap_or(u64::MAX)
+    }
+
+    fn tile_code(&self, value: u64) -> Option<u32> {
+        self.values
+            .iter()
+            .position(|&v| v == value)
+            .map(|tile| tile as u32)
+    }
+
+    fn spawn_tiles(&self) -> Vec<(u32, f64)> {
+        vec![(0, 0.5), (1, 0.5)]
+    }
+
+    /// Sum of the tiles that took a merge.
+    fn score(&self, board: &Board, _merge_score: u64) -> i32 {
+        board
+            .board_data()
+            .iter()
+            .filter_map(|cell| cell.get_score())
+            .filter(|&tile| tile >= 2)
+            .fold(0i32, |score, tile| {
+                let value = self.tile_value(tile).min(i32::MAX as u64) as i32;
+                score.saturating_add(value)
+            })
+    }
+
+    /// The first tile of 1000 or more, the last one of shorter sequences.
+    fn default_target(&self) -> u32 {
+        self.values
+            .iter()
+            .position(|&value| value >= 1000)
+            .unwrap_or(self.values.len() - 1) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::game::Notation;
+
+    fn slide(rules: &dyn Ruleset, row: &str) -> (String, bool) {
+        let mut board = Board::parse(row, Notation::Compact).unwrap();
+        let mut line = board.get_mut_row(0);
+        let moved = rules.slide_line(&mut line, &mut 0);
+        (board.to_notation(Notation::Compact), moved)
+    }
+
+    #[test]
+    fn threes_lines_move_a_single_step() {
+        let cases = [
+            ("01..", "2...", true),
+            ("00..", "00..", false),
+            ("11..", "11..", false),
+            ("22..", "3...", true),
+            (".2.3", "2.3.", true),
+            ("2.3.", "23..", true),
+            ("2332", "242.", true),
+            ("2345", "2345", false),
+            ("1012", "212.", true),
+            ("3...", "3...", false),
+        ];
+        for &(row, expected, moved) in cases.iter() {
+            assert_eq!(
+                slide(&Threes, row),
+                (expected.to_string(), moved),
+                "{}",
+                row
+            );
+        }
+    }
+
+    #[test]
+    fn classic_spawns_land_anywhere_and_start_with_two_tiles() {
+        let mut game = crate::game::Game::with_rules(&Classic, 11, false);
+        assert_eq!(
+            game.get_board()
+                .board_data()
+                .iter()
+                .filter(|c| c.is_set())
+                .count(),
+            2
+        );
+
+        let mut board = Board::parse("..../..../..../...1", Notation::Compact)
+            .unwrap()
+            .with_rules(&Classic);
+        assert_eq!(board.free_spawns(Direction::LEFT).len(), 15);
+        assert!(board.spawn_at(Direction::LEFT, 5, 2));
+        assert_eq!(board.get_cell(1, 1).get_score(), Some(2));
+
+        while let Some(&dir) = game.get_board().legal_moves().first() {
+            game.step(dir);
+            let (x, y) = game.last_spawn().unwrap();
+            let tile = game.get_board().get_cell(x, y).get_score();
+            assert!(tile == Some(1) || tile == Some(2));
+        }
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn sequence_tiles_merge_with_their_neighbours() {
+        let fibonacci = Sequence::fibonacci();
+        let cases = [
+            ("01..", "2...", true),
+            ("10..", "2...", true),
+            ("00..", "1...", true),
+            ("000.", "10..", true),
+            ("0122", "222.", true),
+            ("0011", "111.", true),
+            ("11..", "11..", false),
+            (".2.3", "4...", true),
+            ("24..", "24..", false),
+        ];
+        for &(row, expected, moved) in cases.iter() {
+            assert_eq!(
+                slide(&fibonacci, row),
+                (expected.to_string(), moved),
+                "{}",
+                row
+            );
+        }
+        assert_eq!(fibonacci.tile_value(10), 144);
+        assert_eq!(fibonacci.tile_code(144), Some(10));
+
+        // The last two values have nothing to merge into.
+        let short = by_name("sequence:1,3,4").unwrap();
+        assert_eq!(short.tile_value(2), 4);
+        assert_eq!(slide(short, "12.."), ("12..".to_string(), false));
+        assert_eq!(slide(short, "01.."), ("2...".to_string(), true));
+        // Two 1s make 2, which isn't in this sequence.
+        assert_eq!(slide(short, "00.."), ("00..".to_string(), false));
+
+        for name in [
+            "sequence:",
+            "sequence:1,2",
+            "sequence:1,1,2",
+            "sequence:1,x,3",
+        ]
+        .iter()
+        {
+            assert!(by_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn threes_tiles_round_trip_through_their_values() {
+        for tile in 0..20 {
+            let value = Threes.tile_value(tile);
+            assert_eq!(Threes.tile_code(value), Some(tile));
+        }
+        assert_eq!(Threes.tile_value(2), 3);
+        assert_eq!(Threes.tile_value(7), 96);
+        assert_eq!(Threes.tile_code(4), None);
+        assert_eq!(Threes.tile_code(0), None);
+    }
+
+    #[test]
+    fn threes_deck_deals_equal_numbers_of_each_tile() {
+        let board = Board::parse("..../..../..../....", Notation::Compact).unwrap();
+        let mut deck = Vec::new();
+        let mut counts = [0; 3];
+        for _ in 0..120 {
+            counts[Threes.draw(&mut deck, &board) as usize] += 1;
+        }
+        assert_eq!(counts, [40, 40, 40]);
+    }
+}
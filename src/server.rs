@@ -0,0 +1,202 @@
+use serde_json::Value;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use crate::engine::{new_game, parse_blocks, state_json, suggest_json};
+use crate::game::{Direction, Game};
+
+const WORKERS: usize = 4;
+
+#[derive(Debug, Default, Deserialize)]
+struct NewGame {
+    target: Option<u32>,
+    endless: Option<bool>,
+    rules: Option<String>,
+    /// Compact layout with `#` for blocked cells.
+    blocks: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Move {
+    direction: String,
+}
+
+struct Sessions {
+    next_id: u64,
+    games: HashMap<u64, Game>,
+}
+
+type Shared = Arc<Mutex<Sessions>>;
+
+/// Serves the REST API on localhost, requests are handled by a small pool of worker threads.
+pub fn serve(port: u16) -> Result<(), String> {
+    let server = Arc::new(Server::http(("127.0.0.1", port)).map_err(|e| e.to_string())?);
+    let sessions = Arc::new(Mutex::new(Sessions {
+        next_id: 1,
+        games: HashMap::new(),
+    }));
+    println!("listening on http://127.0.0.1:{}", port);
+
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let sessions = Arc::clone(&sessions);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &sessions);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| "worker thread panicked".to_string())?;
+    }
+    Ok(())
+}
+
+fn handle(mut request: Request, sessions: &Shared) {
+    let mut body = String::new();
+    let response = match request.as_reader().read_to_string(&mut body) {
+        Ok(_) => route(request.method(), request.url(), &body, sessions),
+        Err(e) => Err((400, e.to_string())),
+    };
+    let (code, value) = match response {
+        Ok(value) => (200, value),
+        Err((code, e)) => (code, json!({ "error": e })),
+    };
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(value.to_string())
+        .with_status_code(code)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        eprintln!("failed to respond: {}", e);
+    }
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, (u16, String)> {
+    serde_json::from_str(body).map_err(|e| (400, e.to_string()))
+}
+
+fn query_param<T: std::str::FromStr>(query: &str, name: &str) -> Option<T> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key == name => value.parse().ok(),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+fn route(
+    method: &Method,
+    url: &str,
+    body: &str,
+    sessions: &Shared,
+) -> Result<Value, (u16, String)> {
+    let mut parts = url.splitn(2, '?');
+    let path = parts.next().unwrap_or("");
+    let query = parts.next().unwrap_or("");
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Post, ["games"]) => {
+            let options: NewGame = if body.trim().is_empty() {
+                NewGame::default()
+            } else {
+                parse_body(body)?
+            };
+            let blocks = match options.blocks {
+                Some(layout) => parse_blocks(&layout).map_err(|e| (400, e))?,
+                None => Vec::new(),
+            };
+            let game = new_game(
+                options.rules.as_ref().map(|s| s.as_str()),
+                options.target,
+                options.endless.unwrap_or(false),
+                &blocks,
+            )
+            .map_err(|e| (400, e))?;
+            let mut sessions = lock(sessions);
+            let id = sessions.next_id;
+            sessions.next_id += 1;
+            let mut value = state_json(&game);
+            value["id"] = json!(id);
+            sessions.games.insert(id, game);
+            Ok(value)
+        }
+        (Method::Get, ["games"]) => {
+            let sessions = lock(sessions);
+            let mut ids: Vec<u64> = sessions.games.keys().cloned().collect();
+            ids.sort();
+            Ok(json!({ "games": ids }))
+        }
+        (Method::Get, ["games", id]) => with_game(sessions, id, |id, game| {
+            let mut value = state_json(game);
+            value["id"] = json!(id);
+            Ok(value)
+        }),
+        (Method::Delete, ["games", id]) => {
+            let id = parse_id(id)?;
+            match lock(sessions).games.remove(&id) {
+                Some(_) => Ok(json!({ "id": id })),
+                None => Err((404, format!("no game with id {}", id))),
+            }
+        }
+        (Method::Post, ["games", id, "move"]) => {
+            let body: Move = parse_body(body)?;
+            let dir: Direction = body.direction.parse().map_err(|e| (400, e))?;
+            with_game(sessions, id, |id, game| {
+                if !game.step(dir) {
+                    return Err((409, format!("illegal move {}", dir)));
+                }
+                let mut value = state_json(game);
+                value["id"] = json!(id);
+                Ok(value)
+            })
+        }
+        (Method::Get, ["games", id, "suggest"]) => {
+            // Search on a copy so other requests aren't blocked on the session lock.
+            let game = with_game(sessions, id, |_, game| Ok(game.clone()))?;
+            let agent: Option<String> = query_param(query, "agent");
+            suggest_json(
+                &game,
+                query_param(query, "depth"),
+                query_param(query, "time_ms"),
+                agent.as_ref().map(|s| s.as_str()),
+            )
+            .map_err(|e| (409, e))
+        }
+        _ => Err((404, format!("no route for {:?} {}", method, path))),
+    }
+}
+
+/// A handler that panicked while holding the lock leaves at worst its own game half updated,
+/// the other sessions stay usable.
+fn lock(sessions: &Shared) -> MutexGuard<'_, Sessions> {
+    sessions.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn parse_id(id: &str) -> Result<u64, (u16, String)> {
+    id.parse()
+        .map_err(|_| (400, format!("invalid game id '{}'", id)))
+}
+
+fn with_game<T, F>(sessions: &Shared, id: &str, f: F) -> Result<T, (u16, String)>
+where
+    F: FnOnce(u64, &mut Game) -> Result<T, (u16, String)>,
+{
+    let id = parse_id(id)?;
+    let mut sessions = lock(sessions);
+    match sessions.games.get_mut(&id) {
+        Some(game) => f(id, game),
+        None => Err((404, format!("no game with id {}", id))),
+    }
+}
@@ -0,0 +1,78 @@
+use rand::rngs::StdRng;
+use rand::{prelude::SliceRandom, thread_rng, SeedableRng};
+
+use crate::ai::AI;
+use crate::game::{Board, Direction};
+
+/// Decides where the tile appears after a move.
+pub trait Spawner {
+    fn name(&self) -> &str;
+
+    /// Index among the spawn cells of `dir`, for a `board` that has just slid in `dir`.
+    fn choose_spawn(&mut self, board: &Board, dir: Direction) -> Option<usize>;
+}
+
+/// Any free spawn cell, like a normal game.
+pub struct RandomSpawner;
+
+impl Spawner for RandomSpawner {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn choose_spawn(&mut self, board: &Board, dir: Direction) -> Option<usize> {
+        board.free_spawns(dir).choose(&mut thread_rng()).cloned()
+    }
+}
+
+/// Like `RandomSpawner` but drawing from a fixed seed, so games can be repeated exactly.
+pub struct SeededSpawner {
+    rng: StdRng,
+}
+
+impl SeededSpawner {
+    pub fn new(seed: u64) -> SeededSpawner {
+        SeededSpawner {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Spawner for SeededSpawner {
+    fn name(&self) -> &str {
+        "seeded"
+    }
+
+    fn choose_spawn(&mut self, board: &Board, dir: Direction) -> Option<usize> {
+        board.free_spawns(dir).choose(&mut self.rng).cloned()
+    }
+}
+
+/// Plays the spawns as the adversary the minimax search already assumes.
+pub struct EvilSpawner {
+    depth: u32,
+}
+
+impl EvilSpawner {
+    pub fn new(depth: u32) -> EvilSpawner {
+        EvilSpawner { depth }
+    }
+}
+
+impl Spawner for EvilSpawner {
+    fn name(&self) -> &str {
+        "evil"
+    }
+
+    fn choose_spawn(&mut self, board: &Board, dir: Direction) -> Option<usize> {
+        AI::worst_spawn(board, dir, self.depth)
+    }
+}
+
+pub fn by_name(name: &str, depth: u32) -> Result<Box<dyn Spawner>, String> {
+    match name {
+        "random" => Ok(Box::new(RandomSpawner)),
+        "evil" => Ok(Box::new(EvilSpawner::new(depth))),
+        _ => Err(format!("unknown spawner '{}'", name)),
+    }
+}
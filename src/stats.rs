@@ -0,0 +1,286 @@
+//! Every finished game is appended to a statistics file as a line of JSON. The file lives in the
+//! user's data directory unless `AUTO2048_STATS` names another one.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::game::{Game, GameStatus};
+
+/// Player of the games nobody but a human moved in.
+pub const HUMAN: &str = "human";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameResult {
+    /// Mode the game was played in, like `gui` or `bench`.
+    pub mode: String,
+    pub rules: String,
+    pub width: usize,
+    pub height: usize,
+    /// `HUMAN` or the name of the agent.
+    pub player: String,
+    pub won: bool,
+    pub score: i32,
+    pub merge_score: u64,
+    /// Value of the highest tile.
+    pub max_tile: u64,
+    pub moves: u32,
+    pub seconds: f64,
+}
+
+impl GameResult {
+    pub fn new(game: &Game, mode: &str, player: &str, duration: Duration) -> GameResult {
+        let board = game.get_board();
+        GameResult {
+            mode: mode.to_string(),
+            rules: game.rules().name().to_string(),
+            width: board.width(),
+            height: board.height(),
+            player: player.to_string(),
+            won: game.status() == GameStatus::Won,
+            score: game.get_score(),
+            merge_score: game.merge_score(),
+            max_tile: board
+                .max_tile()
+                .map_or(0, |tile| game.rules().tile_value(tile)),
+            moves: game.moves(),
+            seconds: duration.as_millis() as f64 / 1000.0,
+        }
+    }
+
+    /// The group `game` falls in when played by `player`.
+    pub fn group_of(game: &Game, mode: &str, player: &str) -> Group {
+        let board = game.get_board();
+        Group {
+            mode: mode.to_string(),
+            rules: game.rules().name().to_string(),
+            width: board.width(),
+            height: board.height(),
+            player: player.to_string(),
+        }
+    }
+
+    pub fn group(&self) -> Group {
+        Group {
+            mode: self.mode.clone(),
+            rules: self.rules.clone(),
+            width: self.width,
+            height: self.height,
+            player: self.player.clone(),
+        }
+    }
+}
+
+/// Scores are only compared between games of the same mode, ruleset, board size and player.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Group {
+    pub mode: String,
+    pub rules: String,
+    pub width: usize,
+    pub height: usize,
+    pub player: String,
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}x{}\t{}",
+            self.mode, self.rules, self.width, self.height, self.player
+        )
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{} moves\t{}\t{}x{} {}\t{}",
+            self.score,
+            self.max_tile,
+            self.moves,
+            self.player,
+            self.width,
+            self.height,
+            self.rules,
+            self.mode
+        )
+    }
+}
+
+/// `AUTO2048_STATS`, or `auto2048/stats.jsonl` in the user's data directory.
+pub fn default_path() -> Result<PathBuf, String> {
+    if let Some(path) = env::var_os("AUTO2048_STATS") {
+        return Ok(PathBuf::from(path));
+    }
+    dirs::data_dir()
+        .map(|dir| dir.join("auto2048").join("stats.jsonl"))
+        .ok_or_else(|| "no data directory to keep statistics in".to_string())
+}
+
+/// Appends `results` to the file at `path`, creating it and its directory when needed.
+pub fn record(path: &Path, results: &[GameResult]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut lines = String::new();
+    for result in results {
+        lines += &serde_json::to_string(result).map_err(|e| e.to_string())?;
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    file.write_all(lines.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Every game recorded at `path`, none when nothing was recorded yet. Lines that don't parse
+/// are skipped, a single bad write shouldn't lose the rest.
+pub fn load(path: &Path) -> Result<Vec<GameResult>, String> {
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(s.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// The `count` best games of `group` by score, best first.
+pub fn high_scores<'a>(
+    results: &'a [GameResult],
+    group: &Group,
+    count: usize,
+) -> Vec<&'a GameResult> {
+    let mut sorted: Vec<&GameResult> = results
+        .iter()
+        .filter(|result| result.group() == *group)
+        .collect();
+    sorted.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| b.max_tile.cmp(&a.max_tile))
+    });
+    sorted.truncate(count);
+    sorted
+}
+
+/// Totals of a group of games.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub games: u32,
+    pub wins: u32,
+    pub total_score: i64,
+    pub best_score: i32,
+    pub best_tile: u64,
+    pub moves: u64,
+    pub seconds: f64,
+}
+
+impl Summary {
+    pub fn add(&mut self, result: &GameResult) {
+        self.games += 1;
+        if result.won {
+            self.wins += 1;
+        }
+        self.total_score += result.score as i64;
+        self.best_score = self.best_score.max(result.score);
+        self.best_tile = self.best_tile.max(result.max_tile);
+        self.moves += result.moves as u64;
+        self.seconds += result.seconds;
+    }
+
+    pub fn average_score(&self) -> f64 {
+        self.total_score as f64 / self.games.max(1) as f64
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} games\t{} won\tavg {:.0}\tbest {}\ttile {}\t{} moves\t{:.1}s",
+            self.games,
+            self.wins,
+            self.average_score(),
+            self.best_score,
+            self.best_tile,
+            self.moves,
+            self.seconds
+        )
+    }
+}
+
+/// Totals of every group of games, ordered by mode, ruleset, board size and player.
+pub fn summarize(results: &[GameResult]) -> BTreeMap<Group, Summary> {
+    let mut groups = BTreeMap::new();
+    for result in results {
+        groups
+            .entry(result.group())
+            .or_insert_with(Summary::default)
+            .add(result);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(player: &str, score: i32, won: bool) -> GameResult {
+        GameResult {
+            mode: "bench".to_string(),
+            rules: "edge".to_string(),
+            width: 4,
+            height: 4,
+            player: player.to_string(),
+            won,
+            score,
+            merge_score: 0,
+            max_tile: score as u64 / 2,
+            moves: 10,
+            seconds: 1.5,
+        }
+    }
+
+    #[test]
+    fn recorded_games_load_back_and_summarize() {
+        let path = env::temp_dir().join(format!("auto2048-stats-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(load(&path).unwrap(), Vec::new());
+
+        let results = vec![
+            result("minimax", 2000, true),
+            result(HUMAN, 500, false),
+            result("minimax", 1000, false),
+        ];
+        record(&path, &results[..1]).unwrap();
+        record(&path, &results[1..]).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, results);
+
+        // The human game scored between the two others but is ranked on its own.
+        let minimax = loaded[0].group();
+        let best: Vec<i32> = high_scores(&loaded, &minimax, 5)
+            .iter()
+            .map(|r| r.score)
+            .collect();
+        assert_eq!(best, vec![2000, 1000]);
+        assert_eq!(high_scores(&loaded, &loaded[1].group(), 5).len(), 1);
+
+        let groups = summarize(&loaded);
+        assert_eq!(groups.len(), 2);
+        let minimax = &groups[&minimax];
+        assert_eq!((minimax.games, minimax.wins), (2, 1));
+        assert_eq!(minimax.average_score(), 1500.0);
+        assert_eq!(minimax.best_tile, 1000);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::game::{Board, Direction};
+
+/// One of the 8 symmetries of a rectangle, rotations are clockwise.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Transform {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Transpose,
+    AntiTranspose,
+}
+
+impl Transform {
+    pub fn all() -> [Transform; 8] {
+        [
+            Transform::Identity,
+            Transform::Rotate90,
+            Transform::Rotate180,
+            Transform::Rotate270,
+            Transform::FlipHorizontal,
+            Transform::FlipVertical,
+            Transform::Transpose,
+            Transform::AntiTranspose,
+        ]
+    }
+
+    pub fn inverse(self) -> Transform {
+        match self {
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            t => t,
+        }
+    }
+
+    /// Whether width and height trade places.
+    fn swaps_axes(self) -> bool {
+        match self {
+            Transform::Rotate90
+            | Transform::Rotate270
+            | Transform::Transpose
+            | Transform::AntiTranspose => true,
+            _ => false,
+        }
+    }
+
+    /// Where the cell at `(x, y)` of a `width` by `height` board ends up.
+    pub fn apply(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::Identity => (x, y),
+            Transform::Rotate90 => (height - 1 - y, x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (y, width - 1 - x),
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Transpose => (y, x),
+            Transform::AntiTranspose => (height - 1 - y, width - 1 - x),
+        }
+    }
+
+    /// The move on the transformed board that matches `dir` on the original.
+    pub fn map_direction(self, dir: Direction) -> Direction {
+        let (dx, dy) = match dir {
+            Direction::UP => (0, -1),
+            Direction::DOWN => (0, 1),
+            Direction::LEFT => (-1, 0),
+            Direction::RIGHT => (1, 0),
+        };
+        let (dx, dy) = match self {
+            Transform::Identity => (dx, dy),
+            Transform::Rotate90 => (-dy, dx),
+            Transform::Rotate180 => (-dx, -dy),
+            Transform::Rotate270 => (dy, -dx),
+            Transform::FlipHorizontal => (-dx, dy),
+            Transform::FlipVertical => (dx, -dy),
+            Transform::Transpose => (dy, dx),
+            Transform::AntiTranspose => (-dy, -dx),
+        };
+        match (dx, dy) {
+            (0, -1) => Direction::UP,
+            (0, 1) => Direction::DOWN,
+            (-1, 0) => Direction::LEFT,
+            _ => Direction::RIGHT,
+        }
+    }
+
+    /// The move on the original board that matches `dir` on the transformed one.
+    pub fn unmap_direction(self, dir: Direction) -> Direction {
+        self.inverse().map_direction(dir)
+    }
+}
+
+impl Board {
+    pub fn transformed(&self, transform: Transform) -> Board {
+        let (width, height) = if transform.swaps_axes() {
+            (self.height(), self.width())
+        } else {
+            (self.width(), self.height())
+        };
+        let mut rows = vec![vec![None; width]; height];
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let (tx, ty) = transform.apply(x, y, self.width(), self.height());
+                rows[ty][tx] = self.get_cell(x, y).get_score();
+            }
+        }
+        let mut board = Board::from_exponents(&rows)
+            .unwrap()
+            .with_rules(self.rules());
+        for (x, y) in self.blocked_cells() {
+            let (tx, ty) = transform.apply(x, y, self.width(), self.height());
+            board.get_mut_cell(tx, ty).set_blocked();
+        }
+        board
+    }
+
+    /// The smallest of the boards this one is symmetric to, comparing dimensions and then the
+    /// cells in row order, together with the transform that produces it from this board.
+    pub fn canonical(&self) -> (Board, Transform) {
+        let key = |board: &Board| {
+            let cells: Vec<(bool, Option<u32>)> = (0..board.height())
+                .flat_map(|y| (0..board.width()).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let cell = board.get_cell(x, y);
+                    (cell.is_blocked(), cell.get_score())
+                })
+                .collect();
+            (board.width(), board.height(), cells)
+        };
+        Transform::all()
+            .iter()
+            .map(|&transform| (self.transformed(transform), transform))
+            .min_by_key(|(board, _)| key(board))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::game::tests::random_board;
+
+    const SEED: u64 = 2048;
+
+    fn boards() -> Vec<Board> {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let mut boards: Vec<Board> = (0..50).map(|_| random_board(&mut rng, 4, 4, 0.3)).collect();
+        boards.extend((0..20).map(|_| random_board(&mut rng, 3, 5, 0.3)));
+        for board in boards.iter_mut().step_by(5) {
+            let (x, y) = (
+                rng.gen_range(0, board.width()),
+                rng.gen_range(0, board.height()),
+            );
+            board.get_mut_cell(x, y).set_blocked();
+        }
+        boards
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        for board in boards() {
+            for &t in Transform::all().iter() {
+                assert_eq!(board.transformed(t).transformed(t.inverse()), board);
+            }
+        }
+    }
+
+    #[test]
+    fn directions_round_trip() {
+        for &t in Transform::all().iter() {
+            for &dir in Direction::all().iter() {
+                assert_eq!(t.unmap_direction(t.map_direction(dir)), dir);
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_is_shared_by_symmetric_boards() {
+        for board in boards() {
+            let (canonical, transform) = board.canonical();
+            assert_eq!(board.transformed(transform), canonical);
+            for &t in Transform::all().iter() {
+                assert_eq!(board.transformed(t).canonical().0, canonical);
+            }
+        }
+    }
+
+    #[test]
+    fn moves_commute_with_symmetry() {
+        for board in boards() {
+            for &t in Transform::all().iter() {
+                for &dir in Direction::all().iter() {
+                    let mut moved = board.clone();
+                    let legal = moved.step_rows(dir);
+                    let mut mirrored = board.transformed(t);
+                    let mirrored_legal = mirrored.step_rows(t.map_direction(dir));
+                    assert_eq!(legal, mirrored_legal);
+                    assert_eq!(moved.transformed(t), mirrored);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_commute_with_symmetry() {
+        for board in boards() {
+            for &t in Transform::all().iter() {
+                for &dir in Direction::all().iter() {
+                    let mirrored = board.transformed(t);
+                    let mirrored_dir = t.map_direction(dir);
+                    let mut expected: Vec<Board> = mirrored
+                        .free_spawns(mirrored_dir)
+                        .into_iter()
+                        .map(|index| {
+                            let mut spawned = mirrored.clone();
+                            spawned.spawn_at(mirrored_dir, index, 0);
+                            spawned
+                        })
+                        .collect();
+                    for index in board.free_spawns(dir) {
+                        let mut spawned = board.clone();
+                        spawned.spawn_at(dir, index, 0);
+                        let spawned = spawned.transformed(t);
+                        let found = expected.iter().position(|b| *b == spawned);
+                        expected.remove(found.expect("spawn has no mirrored counterpart"));
+                    }
+                    assert!(expected.is_empty());
+                }
+            }
+        }
+    }
+}
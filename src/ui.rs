@@ -4,14 +4,56 @@ use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::TextureQuery;
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::agent::{self, Agent, SearchAgent, AGENTS};
 use crate::ai::AIScore;
-use crate::ai::AI;
-use crate::game::{Direction, Game};
+use crate::game::{Board, Direction, Game};
+use crate::rules::{self, RULESETS};
+use crate::spawner::{EvilSpawner, RandomSpawner, Spawner};
+use crate::stats::{self, GameResult};
 
 pub struct App {}
 
+/// Rows of the high score table the H key shows.
+const HIGH_SCORES: usize = 10;
+
+/// Block layouts the B key cycles through.
+const LAYOUTS: [&[(usize, usize)]; 3] = [&[], &[(1, 1)], &[(0, 0), (3, 3)]];
+
+/// Index of the spawn cell clicked at `x`, `y` when playing as the spawner.
+fn clicked_spawn(game: &Game, x: i32, y: i32) -> Option<usize> {
+    let dir = game.pending_spawn()?;
+    let board = game.get_board();
+    let rw = 380 / board.width() as i32;
+    let rh = 380 / board.height() as i32;
+    if x < 10 || y < 10 {
+        return None;
+    }
+    let cell = (((x - 10) / rw) as usize, ((y - 10) / rh) as usize);
+    board
+        .spawn_cells(dir)
+        .iter()
+        .position(|&spawn| spawn == cell)
+}
+
+/// Minimax searches less deep the more ways a spawn can go, so moves stay interactive.
+fn minimax_depth(board: &Board) -> u32 {
+    let rules = board.rules();
+    match rules.spawn_count(board, Direction::LEFT) * rules.spawn_tiles().len() {
+        0..=4 => 9,
+        5..=12 => 7,
+        _ => 5,
+    }
+}
+
+fn make_agent(board: &Board, name: &str) -> Box<dyn Agent> {
+    match name {
+        "minimax" => Box::new(SearchAgent::minimax(board, minimax_depth(board))),
+        name => agent::by_name(name, board).unwrap(),
+    }
+}
+
 impl App {
     pub fn new() -> App {
         App {}
@@ -49,6 +91,30 @@ impl App {
         let mut avg = false;
 
         let mut starts = Vec::new();
+        let mut agent = 0;
+        let mut ai = make_agent(game.get_board(), AGENTS[agent]);
+        let mut spawner: Box<dyn Spawner> = Box::new(RandomSpawner);
+        let mut human_spawns = false;
+        let mut ruleset = 0;
+        let mut layout = 0;
+
+        // Finished games go to the statistics file, the clock of a game starts on its first move.
+        let stats_path = stats::default_path();
+        let mut results = match stats_path.as_ref() {
+            Ok(path) => stats::load(path).unwrap_or_else(|e| {
+                println!("failed to read the statistics: {}", e);
+                Vec::new()
+            }),
+            Err(e) => {
+                println!("not keeping statistics: {}", e);
+                Vec::new()
+            }
+        };
+        let mut started = Instant::now();
+        let mut agent_moved = false;
+        let mut recorded = false;
+        // The high scores of the human and of the current agent, or none.
+        let mut scores_page = 0;
 
         'running: loop {
             frame += 1;
@@ -64,25 +130,25 @@ impl App {
                         keycode: Some(Keycode::Left),
                         ..
                     } => {
-                        game.step(Direction::LEFT);
+                        game.step_with(Direction::LEFT, spawner.as_mut());
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::Up),
                         ..
                     } => {
-                        game.step(Direction::UP);
+                        game.step_with(Direction::UP, spawner.as_mut());
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::Down),
                         ..
                     } => {
-                        game.step(Direction::DOWN);
+                        game.step_with(Direction::DOWN, spawner.as_mut());
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::Right),
                         ..
                     } => {
-                        game.step(Direction::RIGHT);
+                        game.step_with(Direction::RIGHT, spawner.as_mut());
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::Space),
@@ -97,38 +163,146 @@ impl App {
                         keycode: Some(Keycode::R),
                         ..
                     } => game.reset(),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::T),
+                        ..
+                    } => {
+                        ruleset = (ruleset + 1) % RULESETS.len();
+                        let rules = rules::by_name(RULESETS[ruleset])?;
+                        let target = rules.default_target();
+                        game = Game::with_blocks(rules, target, false, LAYOUTS[layout])?;
+                        ai = make_agent(game.get_board(), AGENTS[agent]);
+                        println!("rules: {}", RULESETS[ruleset]);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::B),
+                        ..
+                    } => {
+                        layout = (layout + 1) % LAYOUTS.len();
+                        let rules = game.rules();
+                        game = Game::with_blocks(rules, game.target(), false, LAYOUTS[layout])?;
+                        ai = make_agent(game.get_board(), AGENTS[agent]);
+                        println!("blocks: {:?}", LAYOUTS[layout]);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::M),
+                        ..
+                    } => {
+                        agent = (agent + 1) % AGENTS.len();
+                        ai = make_agent(game.get_board(), AGENTS[agent]);
+                        println!("agent: {}", AGENTS[agent]);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::E),
+                        ..
+                    } => {
+                        spawner = match spawner.name() {
+                            "random" => Box::new(EvilSpawner::new(4)),
+                            _ => Box::new(RandomSpawner),
+                        };
+                        println!("spawner: {}", spawner.name());
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::H),
+                        ..
+                    } => scores_page = (scores_page + 1) % 3,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::S),
+                        ..
+                    } => {
+                        human_spawns = !human_spawns;
+                        println!("playing the spawner: {}", human_spawns);
+                    }
+                    Event::MouseButtonDown { x, y, .. } => {
+                        if let Some(index) = clicked_spawn(&game, x, y) {
+                            game.spawn(index);
+                        }
+                    }
                     _ => {}
                 }
             }
             std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
 
-            use std::time::Instant;
-            if auto_run && frame != 0 {
-                let mut ai = AI::new(&game.get_board(), 9);
-                ai.build_tree();
+            if game.moves() == 0 && !game.is_over() {
+                started = Instant::now();
+                agent_moved = false;
+                recorded = false;
+            }
+            // While playing the spawner the agent waits for the tile to be placed.
+            if auto_run && frame != 0 && game.pending_spawn().is_none() {
                 let start = Instant::now();
-                let minimax = ai.minimax(None);
+                let dir = ai.choose_move(&game);
 
                 let start = start.elapsed();
 
                 starts.push(start);
 
-                println!(
-                    "{}\t{}\t{}\t{:?}",
-                    match minimax.get_direction() {
-                        Direction::UP => "Up",
-                        Direction::DOWN => "Down",
-                        Direction::LEFT => "Left",
-                        Direction::RIGHT => "Right",
-                    },
-                    minimax.get_score(),
-                    game.get_board().get_ai_score(),
-                    start
-                );
+                let dir = match dir {
+                    Some(dir) => dir,
+                    None => {
+                        auto_run = false;
+                        continue;
+                    }
+                };
+                match ai.last_search() {
+                    Some(search) => {
+                        let pv: Vec<String> = search
+                            .principal_variation()
+                            .iter()
+                            .map(|step| step.to_string())
+                            .collect();
+                        let scores: Vec<String> = search
+                            .root_scores()
+                            .iter()
+                            .map(|(dir, score)| format!("{}:{}", dir, score))
+                            .collect();
+
+                        println!(
+                            "{}\t{}\t{}\t{:?}\t{}\t[{}]\t{}",
+                            dir,
+                            search.get_score(),
+                            game.get_board().get_ai_score(),
+                            start,
+                            search.stats(),
+                            scores.join(" "),
+                            pv.join(", ")
+                        );
+                    }
+                    None => println!("{}\t{}\t{:?}", dir, game.get_board().get_ai_score(), start),
+                }
 
-                if game.step(minimax.get_direction()) == false {
+                if human_spawns {
+                    game.slide(dir);
+                } else {
+                    game.step_with(dir, spawner.as_mut());
+                }
+                agent_moved = true;
+                if game.is_over() {
+                    println!(
+                        "{:?}\t{}\t{}",
+                        game.status(),
+                        game.get_score(),
+                        game.merge_score()
+                    );
                     auto_run = false;
+                    avg = true;
+                }
+            }
+
+            if game.is_over() && !recorded {
+                recorded = true;
+                let player = if agent_moved {
+                    AGENTS[agent]
+                } else {
+                    stats::HUMAN
+                };
+                let result = GameResult::new(&game, "gui", player, started.elapsed());
+                if let Ok(path) = stats_path.as_ref() {
+                    if let Err(e) = stats::record(path, std::slice::from_ref(&result)) {
+                        println!("failed to record the game: {}", e);
+                    }
                 }
+                results.push(result);
             }
 
             if avg {
@@ -147,6 +321,42 @@ impl App {
             canvas.set_draw_color(Color::RGB(240, 240, 240));
             canvas.clear();
 
+            if scores_page > 0 {
+                let player = if scores_page == 1 {
+                    stats::HUMAN
+                } else {
+                    AGENTS[agent]
+                };
+                let group = GameResult::group_of(&game, "gui", player);
+                let best = stats::high_scores(&results, &group, HIGH_SCORES);
+                let title = format!(
+                    "{} {}x{} {}",
+                    group.rules, group.width, group.height, group.player
+                );
+                let lines = best.iter().enumerate().map(|(i, result)| {
+                    format!(
+                        "{}. {} {} {} moves",
+                        i + 1,
+                        result.score,
+                        result.max_tile,
+                        result.moves
+                    )
+                });
+                for (i, line) in std::iter::once(title).chain(lines).enumerate() {
+                    let surface = font
+                        .render(&line)
+                        .blended(Color::RGBA(0, 0, 0, 255))
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    let rect = Rect::new(10, 10 + 38 * i as i32, 380, 34);
+                    canvas.copy(&texture, None, Some(rect))?;
+                }
+                canvas.present();
+                continue;
+            }
+
             let board = game.get_board();
 
             let rw = 380 / board.width() as i32;
@@ -157,14 +367,18 @@ impl App {
                     let rx = x as i32 * rw;
                     let ry = y as i32 * rh;
                     let rect = Rect::new(rx + 10, ry + 10, rw as u32, rh as u32);
-                    canvas.set_draw_color(Color::RGB(255, 225, 225));
+                    if board.get_cell(x, y).is_blocked() {
+                        canvas.set_draw_color(Color::RGB(90, 90, 90));
+                    } else {
+                        canvas.set_draw_color(Color::RGB(255, 225, 225));
+                    }
                     canvas.fill_rect(rect)?;
                     canvas.set_draw_color(Color::RGB(0, 0, 0));
                     canvas.draw_rect(rect)?;
 
-                    if board.get_cell(x, y).is_set() {
+                    if let Some(tile) = board.get_cell(x, y).get_score() {
                         let surface = font
-                            .render(&board.get_cell(x, y).as_string())
+                            .render(&board.rules().tile_value(tile).to_string())
                             .blended(Color::RGBA(0, 0, 0, 255))
                             .map_err(|e| e.to_string())?;
                         let texture = texture_creator
@@ -179,14 +393,26 @@ impl App {
             let score = game.get_score();
 
             let surface = font
-                .render(&format!("Score: {}", score))
+                .render(&format!("Score: {}  Merged: {}", score, game.merge_score()))
                 .blended(Color::RGBA(0, 0, 0, 255))
                 .map_err(|e| e.to_string())?;
             let texture = texture_creator
                 .create_texture_from_surface(&surface)
                 .map_err(|e| e.to_string())?;
             let TextureQuery { width, height, .. } = texture.query();
-            canvas.copy(&texture, None, Some(Rect::new(150, 400, 100, 40)))?;
+            canvas.copy(&texture, None, Some(Rect::new(50, 400, 300, 40)))?;
+
+            if game.rules().previews_next_tile() {
+                let next = game.rules().tile_value(game.next_tile());
+                let surface = font
+                    .render(&format!("Next: {}", next))
+                    .blended(Color::RGBA(0, 0, 0, 255))
+                    .map_err(|e| e.to_string())?;
+                let texture = texture_creator
+                    .create_texture_from_surface(&surface)
+                    .map_err(|e| e.to_string())?;
+                canvas.copy(&texture, None, Some(Rect::new(150, 450, 100, 40)))?;
+            }
 
             canvas.present();
         }